@@ -0,0 +1,367 @@
+//! Runs a pattern without a window or GPU, and prints statistics about it.
+//!
+//! ```sh
+//! cargo run --bin headless -- assets/patterns/pattern7.pattern.json --ticks 600 --path 0,100 -200,100
+//! ```
+//!
+//! With `--stress`, it times the collision grid against a plain scan instead, and fails if they disagree:
+//!
+//! ```sh
+//! cargo run --release --bin headless -- --stress 60000
+//! ```
+
+use std::{
+    process::exit,
+    time::{Duration, Instant},
+};
+
+use bevy::{ecs::event::ManualEventReader, prelude::*};
+use simidi::{
+    bullet::{grid::BulletGrid, BulletPool, FirePattern, Graze, GrazeRadius, PlayerHit, Playfield},
+    headless::HeadlessSimulation,
+    player::Player,
+    random::{combine, hash_str, RandomStream},
+    simulation::{SimulationApp, SimulationClock, SimulationStage},
+};
+
+const USAGE: &str = "\
+usage: headless <pattern file> [options]
+       headless --stress <bullets> [--ticks <n>]
+
+options:
+    --ticks <n>        simulation steps to run (default 600)
+    --rate <hz>        simulation steps per second (default 60)
+    --seed <n>         seed for the patterns' random functions (default 0)
+    --sample <n>       print statistics every n steps (default 60)
+    --interval <n>     fire the pattern again every n steps
+    --origin <x,y>     where the pattern is fired from (default 0,0)
+    --path <x,y>...    points the player moves through, evenly over the run (default 0,100)
+    --stress <n>       time collision queries over n still bullets, once per tick, without a pattern";
+
+/// Bullet type spread over the playfield by `--stress`
+const STRESS_BULLET_TYPE: &str = "SA_bullet";
+
+struct Options {
+    pattern: String,
+    ticks: u32,
+    rate: f32,
+    seed: u64,
+    sample: u32,
+    interval: Option<u32>,
+    origin: Vec2,
+    path: Vec<Vec2>,
+    stress: Option<usize>,
+}
+
+impl Options {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut pattern = None;
+        let mut options = Options {
+            pattern: String::new(),
+            ticks: 600,
+            rate: 60.,
+            seed: 0,
+            sample: 60,
+            interval: None,
+            origin: Vec2::ZERO,
+            path: vec![],
+            stress: None,
+        };
+
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} expects a value"));
+
+            match arg.as_str() {
+                "--ticks" => options.ticks = parse_number(&value()?)?,
+                "--rate" => options.rate = parse_number(&value()?)?,
+                "--seed" => options.seed = parse_number(&value()?)?,
+                "--sample" => options.sample = parse_number::<u32>(&value()?)?.max(1),
+                "--interval" => options.interval = Some(parse_number::<u32>(&value()?)?.max(1)),
+                "--origin" => options.origin = parse_point(&value()?)?,
+                "--stress" => options.stress = Some(parse_number(&value()?)?),
+                "--path" => {
+                    while let Some(point) = args.next_if(|arg| !arg.starts_with("--")) {
+                        options.path.push(parse_point(&point)?);
+                    }
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+                _ => pattern = Some(arg),
+            }
+        }
+
+        options.pattern = match (pattern, options.stress) {
+            (Some(pattern), _) => pattern,
+            (None, Some(_)) => String::new(),
+            (None, None) => return Err("missing the pattern file".into()),
+        };
+        if options.path.is_empty() {
+            options.path.push(Vec2::new(0., 100.));
+        }
+        Ok(options)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("`{value}` isn't a valid number"))
+}
+
+fn parse_point(value: &str) -> Result<Vec2, String> {
+    let (x, y) = value
+        .split_once(',')
+        .ok_or_else(|| format!("`{value}` isn't a point like `x,y`"))?;
+    Ok(Vec2::new(parse_number(x)?, parse_number(y)?))
+}
+
+/// Moves the player through its points at a constant pace, reaching the last one on the last step.
+#[derive(Resource)]
+struct PlayerPath {
+    points: Vec<Vec2>,
+    ticks: u32,
+}
+
+impl PlayerPath {
+    fn position(&self, tick: u64) -> Vec2 {
+        let segments = self.points.len() - 1;
+        if segments == 0 {
+            return self.points[0];
+        }
+
+        let progress = tick as f32 / self.ticks.saturating_sub(1).max(1) as f32;
+        let along = (progress * segments as f32).min(segments as f32);
+        let i = (along as usize).min(segments - 1);
+        self.points[i].lerp(self.points[i + 1], along - i as f32)
+    }
+
+    fn follow(
+        path: Res<PlayerPath>,
+        clock: Res<SimulationClock>,
+        mut player_query: Query<&mut Transform, With<Player>>,
+    ) {
+        // The clock counts the current step, starting from 1
+        let position = path.position(clock.tick() - 1);
+        for mut player_tr in player_query.iter_mut() {
+            player_tr.translation = position.extend(0.);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    hits: usize,
+    grazes: usize,
+    peak_bullets: usize,
+    bounds: Option<Rect>,
+}
+
+fn main() {
+    let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("{error}\n\n{USAGE}");
+        exit(2)
+    });
+
+    if let Some(bullets) = options.stress {
+        stress(bullets, options.ticks);
+        return;
+    }
+
+    let mut simulation = HeadlessSimulation::new(options.rate);
+    simulation.seed(options.seed);
+    simulation
+        .app
+        .insert_resource(PlayerPath {
+            points: options.path.clone(),
+            ticks: options.ticks,
+        })
+        .add_system_to_simulation(SimulationStage::Input, PlayerPath::follow);
+    simulation.spawn_player(options.path[0]);
+
+    let pattern = simulation
+        .load_pattern(&options.pattern)
+        .unwrap_or_else(|error| {
+            eprintln!("{error}");
+            exit(1)
+        });
+
+    let mut hit_reader = ManualEventReader::<PlayerHit>::default();
+    let mut graze_reader = ManualEventReader::<Graze>::default();
+    let mut stats = Stats::default();
+
+    println!(
+        "{:>6} {:>8} {:>8} {:>6} {:>6}  bounds",
+        "tick", "time", "bullets", "hits", "grazes"
+    );
+
+    for tick in 0..options.ticks {
+        if tick == 0
+            || options
+                .interval
+                .map_or(false, |interval| tick % interval == 0)
+        {
+            simulation.fire(FirePattern::new(pattern.clone()).at(options.origin));
+        }
+
+        simulation.step();
+
+        let world = &simulation.app.world;
+        stats.hits += hit_reader
+            .iter(world.resource::<Events<PlayerHit>>())
+            .count();
+        stats.grazes += graze_reader.iter(world.resource::<Events<Graze>>()).count();
+
+        let (bullets, bounds) = measure(&simulation.pools());
+        stats.peak_bullets = stats.peak_bullets.max(bullets);
+        stats.bounds = union(stats.bounds, bounds);
+
+        let tick = tick + 1;
+        if tick % options.sample == 0 || tick == options.ticks {
+            println!(
+                "{tick:>6} {:>7.2}s {bullets:>8} {:>6} {:>6}  {}",
+                simulation.clock().elapsed(),
+                stats.hits,
+                stats.grazes,
+                format_bounds(bounds),
+            );
+        }
+    }
+
+    println!();
+    println!("peak bullets: {}", stats.peak_bullets);
+    println!("hits: {}, grazes: {}", stats.hits, stats.grazes);
+    println!("overall bounds: {}", format_bounds(stats.bounds));
+    println!("state checksum: {:016x}", checksum(&simulation.pools()));
+}
+
+fn measure(pools: &[&BulletPool]) -> (usize, Option<Rect>) {
+    let mut bullets = 0;
+    let mut bounds = None;
+    for pool in pools {
+        bullets += pool.len();
+        for state in pool.live_states() {
+            let point = Vec2::new(state.x, state.y);
+            bounds = union(bounds, Some(Rect::from_corners(point, point)));
+        }
+    }
+
+    (bullets, bounds)
+}
+
+fn union(a: Option<Rect>, b: Option<Rect>) -> Option<Rect> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(Rect {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn format_bounds(bounds: Option<Rect>) -> String {
+    match bounds {
+        Some(Rect { min, max }) => {
+            format!("x {:.1}..{:.1}, y {:.1}..{:.1}", min.x, max.x, min.y, max.y)
+        }
+        None => "-".into(),
+    }
+}
+
+/// Hash of every live bullet's exact state, to check that runs are identical.
+/// Stays the same between builds, so checksums from different machines can be compared.
+fn checksum(pools: &[&BulletPool]) -> u64 {
+    let mut checksum = 0;
+    for pool in pools {
+        checksum = combine(checksum, hash_str(pool.bullet_type()));
+        for state in pool.live_states() {
+            for value in state.to_array() {
+                checksum = combine(checksum, value.to_bits() as u64);
+            }
+        }
+    }
+    checksum
+}
+
+/// Spreads still bullets over the playfield, then compares finding the ones near random points
+/// with a scan over every bullet and with the grid, rebuilt every time like on a tick.
+fn stress(bullets: usize, ticks: u32) {
+    let mut simulation = HeadlessSimulation::new(60.);
+    simulation.wait_for_assets().unwrap_or_else(|error| {
+        eprintln!("{error}");
+        exit(1)
+    });
+
+    let world = &simulation.app.world;
+    let playfield = *world.resource::<Playfield>();
+    let radius = world.resource::<GrazeRadius>().0;
+    let Rect { min, max } = playfield.rect;
+
+    let mut random = RandomStream::new(0);
+    let mut random_point = move || {
+        Vec2::new(
+            random.range(min.x as f64, max.x as f64) as f32,
+            random.range(min.y as f64, max.y as f64) as f32,
+        )
+    };
+    let positions: Vec<_> = (0..bullets).map(|_| random_point()).collect();
+    simulation.spawn_still_bullets(STRESS_BULLET_TYPE, positions);
+    simulation.step();
+
+    let world = &mut simulation.app.world;
+    let pools: Vec<_> = world.query::<(Entity, &BulletPool)>().iter(world).collect();
+    let live: usize = pools.iter().map(|(_, pool)| pool.len()).sum();
+    let pool = |entity| pools.iter().find(|(e, _)| *e == entity).map(|(_, p)| *p);
+
+    let mut grid = BulletGrid::default();
+    let (mut scan_time, mut rebuild_time, mut query_time) = Default::default();
+    let (mut scan_hits, mut grid_hits, mut candidates) = (0, 0, 0);
+
+    for _ in 0..ticks {
+        let point = random_point();
+
+        let started = Instant::now();
+        for (_, pool) in &pools {
+            let hitbox = pool.definition().hitbox;
+            scan_hits += pool
+                .live_states()
+                .filter(|state| hitbox.overlaps_circle(**state, point, radius))
+                .count();
+        }
+        scan_time += started.elapsed();
+
+        let started = Instant::now();
+        grid.build(&playfield, pools.iter().copied());
+        rebuild_time += started.elapsed();
+
+        let started = Instant::now();
+        for bullet in grid.query_circle(point, radius) {
+            candidates += 1;
+            let Some(pool) = pool(bullet.pool) else { continue };
+            let Some(state) = pool.state(bullet.slot) else { continue };
+            if pool
+                .definition()
+                .hitbox
+                .overlaps_circle(state, point, radius)
+            {
+                grid_hits += 1;
+            }
+        }
+        query_time += started.elapsed();
+    }
+
+    let per_tick = |time: Duration| format!("{:>9.1}µs", time.as_secs_f64() * 1e6 / ticks as f64);
+    println!("{live} bullets, {ticks} queries of radius {radius}");
+    println!("scan:    {} per query", per_tick(scan_time));
+    println!("rebuild: {} per tick", per_tick(rebuild_time));
+    println!(
+        "query:   {} per query, {:.1} candidates on average",
+        per_tick(query_time),
+        candidates as f64 / ticks as f64
+    );
+    println!("hits:    {scan_hits} with the scan, {grid_hits} with the grid");
+
+    if scan_hits != grid_hits {
+        eprintln!("the grid missed bullets the scan found");
+        exit(1);
+    }
+}
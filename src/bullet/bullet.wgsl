@@ -7,7 +7,9 @@
 var<uniform> view: View;
 
 @group(1) @binding(0)
-var<storage, read> positions: array<vec3<f32>>;
+var<storage, read> positions: array<vec4<f32>>;
+@group(1) @binding(1)
+var<storage, read> colors: array<vec4<f32>>;
 
 @group(2) @binding(0)
 var bullet_texture: texture_2d<f32>;
@@ -17,6 +19,7 @@ var bullet_texture_sampler: sampler;
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
 };
 
 @vertex
@@ -44,23 +47,25 @@ fn vertex(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput {
 
     let bullet_pos = positions[bullet_id].xy;
     let bullet_rot = positions[bullet_id].z;
+    let bullet_size = positions[bullet_id].w;
 
     let cos_sin = vec2<f32>(cos(bullet_rot), sin(bullet_rot));
     let rot_matrix = mat2x2<f32>(
         cos_sin.y, cos_sin.x, 
         -cos_sin.x, cos_sin.y);
     
-    let vertex_pos = vertex_positions[vertex_id] * rot_matrix * 16.0;
+    let vertex_pos = vertex_positions[vertex_id] * rot_matrix * bullet_size;
 
     var out: VertexOutput;
     // Calculate the UV from the pattern [[0,0], [1,0], [0,1], [1,1]]
     //out.uv = vec2<f32>(f32(in_vertex_index & 1u), f32((in_vertex_index & 2u) / 2u));
     out.uv = uvs[vertex_id];
+    out.color = colors[bullet_id];
     out.position = view.view_proj * vec4<f32>(bullet_pos + vertex_pos, 0.0, 1.0);
     return out;
 }
 
 @fragment
 fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(bullet_texture, bullet_texture_sampler, in.uv);
+    return textureSample(bullet_texture, bullet_texture_sampler, in.uv) * in.color;
 }
@@ -0,0 +1,502 @@
+use std::fmt;
+use std::path::Path;
+use std::str::from_utf8;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    math::Vec3A,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use serde_json::Value;
+
+use super::grid::distance_to_segment;
+use crate::simulation::SimulationAssets;
+
+#[derive(Default)]
+pub struct BulletTypeLoader;
+
+impl AssetLoader for BulletTypeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            // The sprite defaults to the image next to the definition, with the same name
+            let name = type_name(load_context.path());
+            let default_sprite = load_context
+                .path()
+                .with_file_name(format!("{name}.png"))
+                .to_string_lossy()
+                .into_owned();
+
+            let bullet_type = parse(from_utf8(bytes)?, default_sprite)?;
+            load_context.set_default_asset(LoadedAsset::new(bullet_type));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bullet.json"]
+    }
+}
+
+impl BulletTypeLoader {
+    // Same as the pattern database, every definition gets loaded up front
+    pub(crate) fn init_database(
+        asset_server: Res<AssetServer>,
+        mut bullet_types: ResMut<BulletTypes>,
+        mut simulation_assets: ResMut<SimulationAssets>,
+    ) {
+        if let Ok(definitions) = asset_server
+            .asset_io()
+            .read_directory(Path::new("./bullets"))
+        {
+            for path in definitions {
+                if !path.to_str().unwrap().ends_with("bullet.json") {
+                    continue;
+                }
+
+                let handle = asset_server.load(path.clone());
+                simulation_assets.add(&handle);
+                bullet_types.0.insert(type_name(&path), handle);
+            }
+        }
+    }
+}
+
+/// `bullets/rice.bullet.json` defines the `rice` bullet type.
+fn type_name(path: &Path) -> String {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+        .unwrap_or_default()
+        .into()
+}
+
+/// Bullet type definitions by name, loaded from `assets/bullets/*.bullet.json`.
+/// Patterns pick a type through their bullets' `id`.
+#[derive(Resource, Default)]
+pub struct BulletTypes(pub HashMap<String, Handle<BulletType>>);
+
+impl BulletTypes {
+    pub fn get(&self, name: &str) -> Option<Handle<BulletType>> {
+        self.0.get(name).cloned()
+    }
+}
+
+/// How bullets of one type look and collide.
+/// Bullets whose type has no definition use the defaults, with the sprite `bullets/<id>.png`.
+#[derive(Clone, Debug, TypeUuid)]
+#[uuid = "5b0a1f7e-3c2d-4f8e-9a61-2d7c8e4b9f13"]
+pub struct BulletType {
+    /// Image path, relative to the assets folder
+    pub sprite: String,
+    /// Width and height of the sprite, in world units
+    pub size: f32,
+    pub hitbox: Hitbox,
+    pub blend: BlendMode,
+    /// Tint multiplied with the sprite
+    pub color: Color,
+    /// How fast the sprite turns on itself, in radians per second (degrees in the file).
+    /// Doesn't affect the hitbox.
+    pub spin: f32,
+    /// Test collisions along the whole path a bullet moved through each tick,
+    /// so fast bullets can't skip over the player
+    pub swept: bool,
+}
+
+impl BulletType {
+    pub fn new(sprite: impl Into<String>) -> Self {
+        Self {
+            sprite: sprite.into(),
+            size: 16.,
+            hitbox: Hitbox::default(),
+            blend: BlendMode::default(),
+            color: Color::WHITE,
+            spin: 0.,
+            swept: false,
+        }
+    }
+
+    /// The type used for bullets that don't have a definition file.
+    pub fn fallback(name: &str) -> Self {
+        Self::new(format!("bullets/{name}.png"))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum BlendMode {
+    #[default]
+    Alpha,
+    Additive,
+}
+
+/// The area a bullet collides with, centered on it and turned with it.
+/// `x` lengths run along the bullet's heading and `y` lengths across it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hitbox {
+    Circle {
+        radius: f32,
+    },
+    Ellipse {
+        radii: Vec2,
+    },
+    /// A segment `length` long along the heading, rounded off by `radius`
+    Capsule {
+        length: f32,
+        radius: f32,
+    },
+}
+
+impl Default for Hitbox {
+    fn default() -> Self {
+        Hitbox::Circle { radius: 4. }
+    }
+}
+
+impl Hitbox {
+    /// How far the hitbox reaches from the bullet's center, whichever way it's turned.
+    pub fn extent(&self) -> f32 {
+        match *self {
+            Hitbox::Circle { radius } => radius,
+            Hitbox::Ellipse { radii } => radii.max_element(),
+            Hitbox::Capsule { length, radius } => length / 2. + radius,
+        }
+    }
+
+    /// Whether a circle at `point` touches the hitbox of a bullet with the given state.
+    pub fn overlaps_circle(&self, state: Vec3A, point: Vec2, radius: f32) -> bool {
+        let local = Vec2::from_angle(-state.z).rotate(point - Vec2::new(state.x, state.y));
+
+        match *self {
+            Hitbox::Circle { radius: own } => local.length_squared() < (own + radius).powi(2),
+            // Growing the ellipse by the circle's radius isn't exact,
+            // but it's close enough for circles smaller than the ellipse
+            Hitbox::Ellipse { radii } => (local / (radii + radius)).length_squared() < 1.,
+            Hitbox::Capsule {
+                length,
+                radius: own,
+            } => {
+                let half = length / 2.;
+                let closest = Vec2::new(local.x.clamp(-half, half), 0.);
+                local.distance_squared(closest) < (own + radius).powi(2)
+            }
+        }
+    }
+
+    /// Whether a circle at `point` touches the hitbox anywhere on its way from `from` to `to`.
+    /// The hitbox keeps the heading it has at `to` the whole way.
+    pub fn overlaps_circle_swept(&self, from: Vec3A, to: Vec3A, point: Vec2, radius: f32) -> bool {
+        // Seen from the bullet, the point moves the other way
+        let rotation = Vec2::from_angle(-to.z);
+        let start = rotation.rotate(point - Vec2::new(from.x, from.y));
+        let end = rotation.rotate(point - Vec2::new(to.x, to.y));
+
+        match *self {
+            Hitbox::Circle { radius: own } => {
+                distance_to_segment(Vec2::ZERO, start, end) < own + radius
+            }
+            // Scaling the ellipse into a unit circle keeps the segment a segment
+            Hitbox::Ellipse { radii } => {
+                let scale = radii + radius;
+                distance_to_segment(Vec2::ZERO, start / scale, end / scale) < 1.
+            }
+            Hitbox::Capsule {
+                length,
+                radius: own,
+            } => {
+                let half = length / 2.;
+                segment_distance(Vec2::new(-half, 0.), Vec2::new(half, 0.), start, end)
+                    < own + radius
+            }
+        }
+    }
+}
+
+/// Shortest distance between the segments `a0`-`a1` and `b0`-`b1`.
+fn segment_distance(a0: Vec2, a1: Vec2, b0: Vec2, b1: Vec2) -> f32 {
+    let (a, b) = (a1 - a0, b1 - b0);
+    let denominator = a.perp_dot(b);
+    if denominator != 0. {
+        let t = (b0 - a0).perp_dot(b) / denominator;
+        let u = (b0 - a0).perp_dot(a) / denominator;
+        if (0. ..=1.).contains(&t) && (0. ..=1.).contains(&u) {
+            return 0.;
+        }
+    }
+
+    // Segments that don't cross are closest at one of their ends
+    distance_to_segment(a0, b0, b1)
+        .min(distance_to_segment(a1, b0, b1))
+        .min(distance_to_segment(b0, a0, a1))
+        .min(distance_to_segment(b1, a0, a1))
+}
+
+/// Error raised when a bullet type definition can't be parsed.
+#[derive(Debug)]
+pub struct BulletTypeError {
+    /// Name of the offending field, e.g. `hitbox.radius`. Empty when the whole file is invalid.
+    pub field: String,
+    pub message: String,
+}
+
+impl BulletTypeError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for BulletTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.field.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.field, self.message)
+        }
+    }
+}
+
+impl std::error::Error for BulletTypeError {}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.into()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+pub fn parse(source: &str, default_sprite: String) -> Result<BulletType, BulletTypeError> {
+    let json: Value = serde_json::from_str(source)
+        .map_err(|error| BulletTypeError::new("", format!("invalid JSON: {error}")))?;
+    if !json.is_object() {
+        return Err(BulletTypeError::new("", "expected an object"));
+    }
+
+    let mut bullet_type = BulletType::new(default_sprite);
+
+    match &json["sprite"] {
+        Value::Null => {}
+        Value::String(sprite) => bullet_type.sprite = sprite.clone(),
+        _ => return Err(BulletTypeError::new("sprite", "expected a string")),
+    }
+
+    bullet_type.size = number_or(&json, "", "size", bullet_type.size)?;
+    bullet_type.spin = number_or(&json, "", "spin", 0.)?.to_radians();
+
+    bullet_type.swept = match &json["swept"] {
+        Value::Null => false,
+        Value::Bool(swept) => *swept,
+        _ => return Err(BulletTypeError::new("swept", "expected a boolean")),
+    };
+
+    match &json["hitbox"] {
+        Value::Null => {}
+        hitbox => bullet_type.hitbox = parse_hitbox(hitbox)?,
+    }
+
+    bullet_type.blend = match &json["blend"] {
+        Value::Null => BlendMode::Alpha,
+        Value::String(blend) => match blend.as_str() {
+            "alpha" => BlendMode::Alpha,
+            "additive" => BlendMode::Additive,
+            other => {
+                return Err(BulletTypeError::new(
+                    "blend",
+                    format!("unknown blend mode `{other}`, expected `alpha` or `additive`"),
+                ))
+            }
+        },
+        _ => return Err(BulletTypeError::new("blend", "expected a string")),
+    };
+
+    match &json["color"] {
+        Value::Null => {}
+        color => bullet_type.color = parse_color(color)?,
+    }
+
+    Ok(bullet_type)
+}
+
+fn parse_hitbox(value: &Value) -> Result<Hitbox, BulletTypeError> {
+    let shape = value["shape"]
+        .as_str()
+        .ok_or_else(|| BulletTypeError::new("hitbox.shape", "expected a string"))?;
+
+    Ok(match shape {
+        "circle" => Hitbox::Circle {
+            radius: number(value, "hitbox", "radius")?,
+        },
+        "ellipse" => {
+            let radii = value["radii"]
+                .as_array()
+                .filter(|radii| radii.len() == 2)
+                .and_then(|radii| {
+                    Some(Vec2::new(
+                        radii[0].as_f64()? as f32,
+                        radii[1].as_f64()? as f32,
+                    ))
+                })
+                .ok_or_else(|| BulletTypeError::new("hitbox.radii", "expected two numbers"))?;
+            Hitbox::Ellipse { radii }
+        }
+        "capsule" => Hitbox::Capsule {
+            length: number(value, "hitbox", "length")?,
+            radius: number(value, "hitbox", "radius")?,
+        },
+        other => {
+            return Err(BulletTypeError::new(
+                "hitbox.shape",
+                format!("unknown shape `{other}`, expected `circle`, `ellipse` or `capsule`"),
+            ))
+        }
+    })
+}
+
+/// Colors are either `[r, g, b]` / `[r, g, b, a]` from 0 to 1, or a hex string like `"#ff8000"`.
+fn parse_color(value: &Value) -> Result<Color, BulletTypeError> {
+    let invalid = || BulletTypeError::new("color", "expected 3 or 4 numbers, or a hex string");
+
+    match value {
+        Value::String(hex) => Color::hex(hex.trim_start_matches('#')).map_err(|_| invalid()),
+        Value::Array(channels) if channels.len() == 3 || channels.len() == 4 => {
+            let channels = channels
+                .iter()
+                .map(|channel| channel.as_f64().map(|c| c as f32))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(invalid)?;
+            Ok(Color::rgba(
+                channels[0],
+                channels[1],
+                channels[2],
+                channels.get(3).copied().unwrap_or(1.),
+            ))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn number(value: &Value, path: &str, key: &str) -> Result<f32, BulletTypeError> {
+    value[key]
+        .as_f64()
+        .map(|number| number as f32)
+        .ok_or_else(|| BulletTypeError::new(join_path(path, key), "expected a number"))
+}
+
+fn number_or(value: &Value, path: &str, key: &str, default: f32) -> Result<f32, BulletTypeError> {
+    match &value[key] {
+        Value::Null => Ok(default),
+        _ => number(value, path, key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::bullet::{
+        pattern::BulletVariables, BulletPool, Contact, NewBullet, Playfield, SpawnBullets,
+    };
+
+    /// A bullet at `(10, -5)`, turned by `angle`, and the directions along and across its heading.
+    fn bullet(angle: f32) -> (Vec3A, Vec2, Vec2) {
+        let heading = Vec2::from_angle(angle);
+        (Vec3A::new(10., -5., angle), heading, heading.perp())
+    }
+
+    #[test]
+    fn hitboxes_turn_with_the_bullet() {
+        let ellipse = Hitbox::Ellipse {
+            radii: Vec2::new(20., 4.),
+        };
+        let capsule = Hitbox::Capsule {
+            length: 30.,
+            radius: 3.,
+        };
+
+        for angle in [1., -2.5] {
+            let (state, along, across) = bullet(angle);
+            let position = Vec2::new(state.x, state.y);
+            let overlaps =
+                |hitbox: Hitbox, offset: Vec2| hitbox.overlaps_circle(state, position + offset, 1.);
+
+            assert!(overlaps(ellipse, along * 18.), "{angle}");
+            assert!(overlaps(ellipse, -along * 18.), "{angle}");
+            assert!(!overlaps(ellipse, across * 8.), "{angle}");
+            assert!(overlaps(ellipse, across * 4.), "{angle}");
+
+            assert!(overlaps(capsule, along * 17.), "{angle}");
+            assert!(!overlaps(capsule, -along * 20.), "{angle}");
+            assert!(!overlaps(capsule, across * 5.), "{angle}");
+            assert!(overlaps(capsule, along * 10. + across * 3.), "{angle}");
+        }
+    }
+
+    #[test]
+    fn swept_bullets_hit_what_they_skip_over() {
+        for hitbox in [
+            r#"{"shape": "circle", "radius": 3}"#,
+            r#"{"shape": "ellipse", "radii": [6, 3]}"#,
+            r#"{"shape": "capsule", "length": 12, "radius": 2}"#,
+        ] {
+            for swept in [true, false] {
+                let source = format!(r#"{{"hitbox": {hitbox}, "swept": {swept}}}"#);
+                let definition = parse(&source, "test.png".into()).unwrap();
+                let mut pool = BulletPool::new("test".into(), definition, None, Handle::default());
+
+                // Moves 40 units a step, from one side of the player to the other
+                let bullet = NewBullet {
+                    position: Vec2::new(-20., 0.),
+                    rotation: 0.,
+                    speed: 2400.,
+                    angular: 0.,
+                    lifetime: f32::INFINITY,
+                    variables: BulletVariables::default(),
+                };
+                let spawn = SpawnBullets {
+                    bullet_type: "test".into(),
+                    bullets: vec![],
+                    modifiers: Arc::new([]),
+                    params: Arc::default(),
+                    instance: Entity::from_raw(0),
+                };
+                pool.add(&bullet, &spawn);
+                pool.tick(1. / 60., &Playfield::default());
+
+                let hit = matches!(
+                    pool.check_collision(0, Vec2::ZERO, 0.),
+                    Some(Contact::Hit(_))
+                );
+                assert_eq!(hit, swept, "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn segment_distances() {
+        let distance = |a0: [f32; 2], a1: [f32; 2], b0: [f32; 2], b1: [f32; 2]| {
+            segment_distance(a0.into(), a1.into(), b0.into(), b1.into())
+        };
+
+        // Crossing
+        assert_eq!(distance([-1., 0.], [1., 0.], [0., -1.], [0., 1.]), 0.);
+        // Not crossing, closest at an end
+        assert_eq!(distance([-1., 0.], [1., 0.], [3., -1.], [3., 1.]), 2.);
+        // Parallel
+        assert_eq!(distance([0., 0.], [4., 0.], [1., 3.], [2., 3.]), 3.);
+        assert_eq!(
+            distance([0., 0.], [4., 0.], [6., 3.], [9., 3.]),
+            13_f32.sqrt()
+        );
+        // On the same line, overlapping or not
+        assert_eq!(distance([0., 0.], [4., 0.], [2., 0.], [6., 0.]), 0.);
+        assert_eq!(distance([0., 0.], [4., 0.], [7., 0.], [9., 0.]), 3.);
+    }
+}
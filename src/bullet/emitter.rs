@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+
+use crate::{player::Player, random::SimulationRng, simulation::SimulationClock};
+
+use super::pattern::{FiredBy, Pattern, PatternParams};
+
+/// Fires `pattern` from this entity's position and facing whenever [`Emitter::fire`] is called.
+#[derive(Component)]
+pub struct Emitter {
+    pub pattern: Handle<Pattern>,
+    pub params: PatternParams,
+    queued: u32,
+}
+
+impl Emitter {
+    pub fn new(pattern: Handle<Pattern>) -> Self {
+        Self {
+            pattern,
+            params: PatternParams::default(),
+            queued: 0,
+        }
+    }
+
+    pub fn with_params(mut self, params: PatternParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Queues the pattern to be fired on the next simulation step.
+    pub fn fire(&mut self) {
+        self.queued += 1;
+    }
+
+    pub(crate) fn fire_emitters(
+        mut commands: Commands,
+        patterns: Res<Assets<Pattern>>,
+        mut emitter_query: Query<(Entity, &mut Emitter), With<Transform>>,
+        transform_query: Query<(&Transform, Option<&Parent>)>,
+        mut rng: ResMut<SimulationRng>,
+        player_query: Query<&Transform, With<Player>>,
+    ) {
+        let player = player_query
+            .get_single()
+            .ok()
+            .map(|player_tr| player_tr.translation.truncate());
+
+        for (entity, mut emitter) in emitter_query.iter_mut() {
+            if emitter.queued == 0 {
+                continue;
+            }
+
+            // Shots stay queued until the pattern has loaded
+            let Some(pattern) = patterns.get(&emitter.pattern) else { continue };
+            let emitter_tr = simulated_transform(entity, &transform_query);
+
+            for _ in 0..emitter.queued {
+                let instance = pattern.fire_at(
+                    &mut commands,
+                    emitter_tr,
+                    &emitter.params,
+                    player,
+                    rng.next_u64(),
+                );
+                commands.entity(instance.0).insert(FiredBy(entity));
+            }
+            emitter.queued = 0;
+        }
+    }
+}
+
+/// Fires `pattern` from this entity every `interval` seconds while `enabled`,
+/// until it has fired `count` times or `duration` seconds have passed.
+#[derive(Component)]
+pub struct BulletEmitter {
+    pub pattern: Handle<Pattern>,
+    pub params: PatternParams,
+    pub interval: f32,
+    /// Number of times to fire, or `None` to keep firing
+    pub count: Option<u32>,
+    /// Time to keep firing for, or `None` to keep firing
+    pub duration: Option<f32>,
+    pub enabled: bool,
+
+    age: f32,
+    cooldown: f32,
+    fired: u32,
+}
+
+impl BulletEmitter {
+    pub fn new(pattern: Handle<Pattern>, interval: f32) -> Self {
+        Self {
+            pattern,
+            params: PatternParams::default(),
+            interval,
+            count: None,
+            duration: None,
+            enabled: true,
+            age: 0.,
+            cooldown: 0.,
+            fired: 0,
+        }
+    }
+
+    pub fn with_params(mut self, params: PatternParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn with_count(mut self, count: u32) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    pub fn with_duration(mut self, duration: f32) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.count.map_or(false, |count| self.fired >= count)
+            || self.duration.map_or(false, |duration| self.age >= duration)
+    }
+
+    /// Starts firing again from scratch.
+    pub fn reset(&mut self) {
+        self.age = 0.;
+        self.cooldown = 0.;
+        self.fired = 0;
+    }
+
+    pub(crate) fn tick_emitters(
+        mut commands: Commands,
+        patterns: Res<Assets<Pattern>>,
+        clock: Res<SimulationClock>,
+        mut emitter_query: Query<(Entity, &mut BulletEmitter), With<Transform>>,
+        transform_query: Query<(&Transform, Option<&Parent>)>,
+        mut rng: ResMut<SimulationRng>,
+        player_query: Query<&Transform, With<Player>>,
+    ) {
+        let player = player_query
+            .get_single()
+            .ok()
+            .map(|player_tr| player_tr.translation.truncate());
+
+        for (entity, mut emitter) in emitter_query.iter_mut() {
+            if !emitter.enabled || emitter.is_finished() {
+                continue;
+            }
+
+            // Don't start the clock until the pattern has loaded
+            let Some(pattern) = patterns.get(&emitter.pattern) else { continue };
+            let emitter_tr = simulated_transform(entity, &transform_query);
+
+            emitter.age += clock.step;
+            emitter.cooldown -= clock.step;
+
+            while emitter.cooldown <= 0. && !emitter.is_finished() {
+                let instance = pattern.fire_at(
+                    &mut commands,
+                    emitter_tr,
+                    &emitter.params,
+                    player,
+                    rng.next_u64(),
+                );
+                commands.entity(instance.0).insert(FiredBy(entity));
+                emitter.fired += 1;
+
+                // Never fire more than once per step without an interval
+                if emitter.interval <= 0. {
+                    emitter.cooldown = 0.;
+                    break;
+                }
+                emitter.cooldown += emitter.interval;
+            }
+        }
+    }
+}
+
+/// Where an entity is in the world, from its own and its parents' transforms.
+/// `GlobalTransform` is only updated after the simulation has stepped, so it can be a frame behind.
+fn simulated_transform(
+    entity: Entity,
+    transform_query: &Query<(&Transform, Option<&Parent>)>,
+) -> Transform {
+    let mut transform = Transform::IDENTITY;
+    let mut current = Some(entity);
+    while let Some((local, parent)) = current.and_then(|entity| transform_query.get(entity).ok()) {
+        transform = local.mul_transform(transform);
+        current = parent.map(Parent::get);
+    }
+    transform
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+    use crate::headless::HeadlessSimulation;
+
+    #[test]
+    fn fires_from_the_simulated_transform() {
+        let mut simulation = HeadlessSimulation::new(60.);
+        let pattern = simulation
+            .load_pattern("patterns/pattern1.pattern.json")
+            .unwrap();
+
+        // Spawned right before the step, before any GlobalTransform has been computed
+        let world = &mut simulation.app.world;
+        let parent =
+            Transform::from_xyz(100., 0., 0.).with_rotation(Quat::from_rotation_z(FRAC_PI_2));
+        let parent = world.spawn(TransformBundle::from(parent)).id();
+        let emitter = world
+            .spawn((
+                TransformBundle::from(Transform::from_xyz(0., 50., 0.)),
+                BulletEmitter::new(pattern, 1.).with_count(1),
+            ))
+            .id();
+        world.entity_mut(parent).push_children(&[emitter]);
+        simulation.step();
+
+        let origin = Vec2::new(50., 0.);
+        let bullets: Vec<_> = simulation
+            .pools()
+            .iter()
+            .flat_map(|pool| pool.live_states())
+            .collect();
+        assert!(!bullets.is_empty());
+        for bullet in bullets {
+            let distance = bullet.truncate().distance(origin);
+            assert!(
+                distance < 2.,
+                "{bullet} is {distance} away from the emitter"
+            );
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use bevy::prelude::*;
+
+use super::{BulletPool, Playfield};
+
+/// A bullet, by the pool it lives in and its slot there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BulletRef {
+    pub pool: Entity,
+    pub slot: usize,
+}
+
+/// Uniform grid over the playfield, rebuilt every tick, to find the bullets near something
+/// without going through all of them.
+///
+/// Queries return every bullet whose hitbox might reach the queried area,
+/// so callers still have to test the hitboxes themselves.
+#[derive(Resource)]
+pub struct BulletGrid {
+    /// Width and height of a cell. Takes effect on the next rebuild.
+    pub cell_size: f32,
+    origin: Vec2,
+    size: UVec2,
+    /// Where each cell's bullets start in `bullets`, followed by the total count
+    cell_starts: Vec<u32>,
+    bullets: Vec<BulletRef>,
+    /// Largest distance from a bullet's center to the edge of its hitbox
+    max_extent: f32,
+    /// Reused between rebuilds to avoid allocating
+    scratch: Vec<(u32, BulletRef)>,
+}
+
+impl Default for BulletGrid {
+    fn default() -> Self {
+        Self {
+            cell_size: 32.,
+            origin: Vec2::ZERO,
+            size: UVec2::ONE,
+            cell_starts: vec![0, 0],
+            bullets: vec![],
+            max_extent: 0.,
+            scratch: vec![],
+        }
+    }
+}
+
+impl BulletGrid {
+    pub(crate) fn rebuild(
+        mut grid: ResMut<BulletGrid>,
+        playfield: Res<Playfield>,
+        pool_query: Query<(Entity, &BulletPool)>,
+    ) {
+        grid.build(&playfield, pool_query.iter());
+    }
+
+    /// Sorts every live bullet into the cell under its center, with a counting sort.
+    pub fn build<'a>(
+        &mut self,
+        playfield: &Playfield,
+        pools: impl Iterator<Item = (Entity, &'a BulletPool)>,
+    ) {
+        let min = playfield.rect.min - playfield.margin;
+        let max = playfield.rect.max + playfield.margin;
+        self.origin = min;
+        self.size = ((max - min) / self.cell_size)
+            .ceil()
+            .as_uvec2()
+            .max(UVec2::ONE);
+
+        let cell_count = (self.size.x * self.size.y) as usize;
+        self.cell_starts.clear();
+        self.cell_starts.resize(cell_count + 1, 0);
+        self.max_extent = 0.;
+
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.clear();
+
+        for (entity, pool) in pools {
+            if pool.is_empty() {
+                continue;
+            }
+
+            let extent = pool.definition.hitbox.extent();
+            self.max_extent = self.max_extent.max(extent);
+            for (slot, state) in pool.states.iter().enumerate() {
+                if pool.alive[slot] {
+                    // Swept bullets reach back to where they were on the last tick
+                    if pool.definition.swept {
+                        let travel = (*state - pool.previous[slot]).truncate().length();
+                        self.max_extent = self.max_extent.max(extent + travel);
+                    }
+
+                    let cell = self.cell_index(self.cell_of(Vec2::new(state.x, state.y)));
+                    self.cell_starts[cell + 1] += 1;
+                    scratch.push((cell as u32, BulletRef { pool: entity, slot }));
+                }
+            }
+        }
+
+        for i in 1..self.cell_starts.len() {
+            self.cell_starts[i] += self.cell_starts[i - 1];
+        }
+
+        // Fill each cell from its start, using the following cell's start as a cursor
+        self.bullets.clear();
+        self.bullets.resize(
+            scratch.len(),
+            BulletRef {
+                pool: Entity::from_raw(0),
+                slot: 0,
+            },
+        );
+        let mut cursors = self.cell_starts.clone();
+        for (cell, bullet) in scratch.iter() {
+            let cursor = &mut cursors[*cell as usize];
+            self.bullets[*cursor as usize] = *bullet;
+            *cursor += 1;
+        }
+
+        self.scratch = scratch;
+    }
+
+    /// Bullets that might touch a circle.
+    pub fn query_circle(&self, center: Vec2, radius: f32) -> impl Iterator<Item = BulletRef> + '_ {
+        let reach = Vec2::splat(radius + self.max_extent);
+        self.cells_between(center - reach, center + reach)
+            .flat_map(|cell| self.cell(cell).iter().copied())
+    }
+
+    /// Bullets that might touch a point.
+    pub fn query_point(&self, point: Vec2) -> impl Iterator<Item = BulletRef> + '_ {
+        self.query_circle(point, 0.)
+    }
+
+    /// Bullets that might touch a segment thickened by `radius`, like a laser or a swept circle.
+    pub fn query_segment(
+        &self,
+        start: Vec2,
+        end: Vec2,
+        radius: f32,
+    ) -> impl Iterator<Item = BulletRef> + '_ {
+        let reach = radius + self.max_extent;
+
+        // Skip the cells of the bounding box that are too far from the segment itself
+        self.cells_between(start.min(end) - reach, start.max(end) + reach)
+            .filter(move |cell| {
+                let (min, max) = self.cell_bounds(*cell);
+                segment_crosses_box(start, end, min - reach, max + reach)
+            })
+            .flat_map(|cell| self.cell(cell).iter().copied())
+    }
+
+    pub fn len(&self) -> usize {
+        self.bullets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bullets.is_empty()
+    }
+
+    fn cell_of(&self, point: Vec2) -> UVec2 {
+        ((point - self.origin) / self.cell_size)
+            .floor()
+            .as_ivec2()
+            .clamp(IVec2::ZERO, self.size.as_ivec2() - 1)
+            .as_uvec2()
+    }
+
+    fn cell_index(&self, cell: UVec2) -> usize {
+        (cell.y * self.size.x + cell.x) as usize
+    }
+
+    /// Corners of the area whose bullets end up in a cell. Bullets outside of the grid
+    /// are clamped into its edge cells, so those reach out forever.
+    fn cell_bounds(&self, index: usize) -> (Vec2, Vec2) {
+        let cell = UVec2::new(index as u32 % self.size.x, index as u32 / self.size.x);
+        let mut min = self.origin + cell.as_vec2() * self.cell_size;
+        let mut max = min + self.cell_size;
+
+        let last = self.size - 1;
+        for axis in 0..2 {
+            if cell[axis] == 0 {
+                min[axis] = f32::NEG_INFINITY;
+            }
+            if cell[axis] == last[axis] {
+                max[axis] = f32::INFINITY;
+            }
+        }
+        (min, max)
+    }
+
+    fn cell(&self, index: usize) -> &[BulletRef] {
+        &self.bullets[self.cell_starts[index] as usize..self.cell_starts[index + 1] as usize]
+    }
+
+    fn cells_between(&self, min: Vec2, max: Vec2) -> impl Iterator<Item = usize> + '_ {
+        let (min, max) = (self.cell_of(min), self.cell_of(max));
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| self.cell_index(UVec2::new(x, y))))
+    }
+}
+
+/// Whether the segment between `start` and `end` goes through the box from `min` to `max`.
+fn segment_crosses_box(start: Vec2, end: Vec2, min: Vec2, max: Vec2) -> bool {
+    let delta = end - start;
+    let (mut enter, mut exit) = (0_f32, 1_f32);
+    for axis in 0..2 {
+        if delta[axis] == 0. {
+            if start[axis] < min[axis] || start[axis] > max[axis] {
+                return false;
+            }
+        } else {
+            let t0 = (min[axis] - start[axis]) / delta[axis];
+            let t1 = (max[axis] - start[axis]) / delta[axis];
+            enter = enter.max(t0.min(t1));
+            exit = exit.min(t0.max(t1));
+        }
+    }
+    enter <= exit
+}
+
+/// Distance from `point` to the closest point of the segment between `start` and `end`.
+pub fn distance_to_segment(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let segment = end - start;
+    let length_sqr = segment.length_squared();
+    if length_sqr == 0. {
+        return point.distance(start);
+    }
+
+    let t = ((point - start).dot(segment) / length_sqr).clamp(0., 1.);
+    point.distance(start + segment * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, f64::consts::TAU, sync::Arc};
+
+    use bevy::math::Vec3A;
+
+    use super::*;
+    use crate::{
+        bullet::{
+            bullet_type::{BulletType, Hitbox},
+            pattern::BulletVariables,
+            NewBullet, SpawnBullets,
+        },
+        random::RandomStream,
+    };
+
+    const POOL_SIZE: usize = 500;
+
+    /// Bullets spread over twice the playfield, so some are outside of the grid and get clamped
+    /// into its edge cells. Swept ones moved up to 200 units on the last tick.
+    fn pool(hitbox: Hitbox, swept: bool, random: &mut RandomStream) -> BulletPool {
+        let mut definition = BulletType::new("test.png");
+        definition.hitbox = hitbox;
+        definition.swept = swept;
+        let mut pool = BulletPool::new("test".into(), definition, None, Handle::default());
+
+        let spawn = SpawnBullets {
+            bullet_type: "test".into(),
+            bullets: vec![],
+            modifiers: Arc::new([]),
+            params: Arc::default(),
+            instance: Entity::from_raw(0),
+        };
+        for _ in 0..POOL_SIZE {
+            let bullet = NewBullet {
+                position: Vec2::new(
+                    random.range(-960., 960.) as f32,
+                    random.range(-540., 540.) as f32,
+                ),
+                rotation: random.range(0., TAU) as f32,
+                speed: 0.,
+                angular: 0.,
+                lifetime: f32::INFINITY,
+                variables: BulletVariables::default(),
+            };
+            pool.add(&bullet, &spawn);
+        }
+
+        if swept {
+            for (previous, state) in pool.previous.iter_mut().zip(&pool.states) {
+                let travel = Vec2::from_angle(state.z) * random.range(0., 200.) as f32;
+                *previous = *state - Vec3A::from(travel.extend(0.));
+            }
+        }
+        pool
+    }
+
+    fn pools() -> Vec<(Entity, BulletPool)> {
+        let mut random = RandomStream::new(0);
+        [
+            (Hitbox::Circle { radius: 4. }, false),
+            // Long shapes reach well outside the cell their center is in
+            (
+                Hitbox::Capsule {
+                    length: 100.,
+                    radius: 3.,
+                },
+                false,
+            ),
+            (
+                Hitbox::Ellipse {
+                    radii: Vec2::new(60., 5.),
+                },
+                false,
+            ),
+            (Hitbox::Circle { radius: 4. }, true),
+            (
+                Hitbox::Capsule {
+                    length: 20.,
+                    radius: 2.,
+                },
+                true,
+            ),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (hitbox, swept))| (Entity::from_raw(i as u32), pool(hitbox, swept, &mut random)))
+        .collect()
+    }
+
+    /// Every bullet touching a circle, going through all of them.
+    fn touching(pools: &[(Entity, BulletPool)], point: Vec2, radius: f32) -> Vec<BulletRef> {
+        let mut touching = vec![];
+        for (entity, pool) in pools {
+            let hitbox = pool.definition.hitbox;
+            for slot in 0..pool.capacity() {
+                let (previous, state) = (pool.previous[slot], pool.states[slot]);
+                let touches = if pool.definition.swept {
+                    hitbox.overlaps_circle_swept(previous, state, point, radius)
+                } else {
+                    hitbox.overlaps_circle(state, point, radius)
+                };
+                if touches {
+                    touching.push(BulletRef {
+                        pool: *entity,
+                        slot,
+                    });
+                }
+            }
+        }
+        touching
+    }
+
+    fn build(pools: &[(Entity, BulletPool)]) -> BulletGrid {
+        let mut grid = BulletGrid::default();
+        grid.build(
+            &Playfield::default(),
+            pools.iter().map(|(entity, pool)| (*entity, pool)),
+        );
+        assert_eq!(grid.len(), pools.len() * POOL_SIZE);
+        grid
+    }
+
+    fn random_point(random: &mut RandomStream) -> Vec2 {
+        Vec2::new(
+            random.range(-1000., 1000.) as f32,
+            random.range(-600., 600.) as f32,
+        )
+    }
+
+    #[test]
+    fn circle_queries_find_every_touching_bullet() {
+        let pools = pools();
+        let grid = build(&pools);
+
+        let mut random = RandomStream::new(1);
+        let mut found = 0;
+        for _ in 0..500 {
+            let point = random_point(&mut random);
+            let radius = random.range(0., 40.) as f32;
+
+            let candidates: HashSet<_> = grid
+                .query_circle(point, radius)
+                .map(|bullet| (bullet.pool, bullet.slot))
+                .collect();
+            for bullet in touching(&pools, point, radius) {
+                assert!(
+                    candidates.contains(&(bullet.pool, bullet.slot)),
+                    "{bullet:?} touches a circle of radius {radius} at {point}, but wasn't found"
+                );
+                found += 1;
+            }
+        }
+        // Otherwise the test wouldn't check much
+        assert!(found > 500, "only {found} bullets were touched");
+    }
+
+    #[test]
+    fn segment_queries_find_every_touching_bullet() {
+        let pools = pools();
+        let grid = build(&pools);
+
+        let mut random = RandomStream::new(2);
+        let mut found = 0;
+        for _ in 0..200 {
+            let start = random_point(&mut random);
+            let end = start + random_point(&mut random) / 4.;
+            let radius = random.range(0., 20.) as f32;
+
+            let candidates: HashSet<_> = grid
+                .query_segment(start, end, radius)
+                .map(|bullet| (bullet.pool, bullet.slot))
+                .collect();
+            // Circles along the segment touch part of what the thickened segment does
+            for i in 0..=64 {
+                let point = start.lerp(end, i as f32 / 64.);
+                for bullet in touching(&pools, point, radius) {
+                    assert!(
+                        candidates.contains(&(bullet.pool, bullet.slot)),
+                        "{bullet:?} touches the segment from {start} to {end}, but wasn't found"
+                    );
+                    found += 1;
+                }
+            }
+        }
+        assert!(found > 500, "only {found} bullets were touched");
+    }
+}
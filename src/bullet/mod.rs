@@ -1,141 +1,432 @@
+pub mod bullet_type;
+pub mod emitter;
+pub mod grid;
 pub mod pattern;
 mod render;
 
-use std::{collections::BTreeMap, ops::Range, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc};
 
-use bevy::{math::Vec3A, prelude::*};
-use fasteval::StrToF64Namespace;
+use bevy::{ecs::system::Command, math::Vec3A, prelude::*};
 use rayon::prelude::*;
 
 use crate::{
-    editor::{is_ui_unfocused, EditorState},
-    player::Player,
+    player::{Lives, Player},
+    random::SimulationRng,
+    replay::StepInput,
+    simulation::{SimulationApp, SimulationClock, SimulationStage},
 };
 
 use self::{
-    pattern::{ExpressionSlab, Pattern, PatternDatabase, PatternLoader},
-    render::BulletPipelinePlugin,
+    bullet_type::{BulletType, BulletTypeLoader, BulletTypes},
+    emitter::{BulletEmitter, Emitter},
+    grid::BulletGrid,
+    pattern::{
+        BulletVariables, ExpressionSlab, FiredBy, Pattern, PatternDatabase, PatternInstance,
+        PatternLoader, PatternParams,
+    },
 };
 
+pub use self::render::BulletPipelinePlugin;
+
 const PLAYER_RADIUS: f32 = 5.;
-const PLAYER_RADIUS_SQR: f32 = PLAYER_RADIUS * PLAYER_RADIUS;
 
+/// Simulates patterns and bullets. Drawing them takes a [`BulletPipelinePlugin`] as well.
 pub struct BulletPlugin;
 
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<PatternDatabase>()
-            // .add_plugin(BulletRenderPlugin)
-            .add_plugin(BulletPipelinePlugin)
             .add_asset::<Pattern>()
+            .add_asset::<BulletType>()
             .add_startup_system(PatternLoader::init_database)
+            .add_startup_system(BulletTypeLoader::init_database)
             // .add_startup_system(BulletPool::create_pool)
             .init_asset_loader::<PatternLoader>()
+            .init_asset_loader::<BulletTypeLoader>()
             .init_resource::<BulletPools>()
-            .add_system(BulletPool::tick_pools)
-            .add_system(BulletPool::free_pools)
-            .add_system(spawn_bullets.with_run_criteria(is_ui_unfocused));
+            .init_resource::<Playfield>()
+            .init_resource::<BulletTypes>()
+            .init_resource::<GrazeRadius>()
+            .init_resource::<BulletGrid>()
+            .add_simulation_event::<FirePattern>()
+            .add_simulation_event::<PlayerHit>()
+            .add_simulation_event::<Graze>()
+            .add_system(BulletPool::apply_definitions)
+            .add_system_to_simulation(SimulationStage::Spawn, PatternInstance::tick_instances)
+            // Everything that fires patterns draws their seeds in a fixed order
+            .add_system_to_simulation(SimulationStage::Spawn, Emitter::fire_emitters)
+            .add_system_to_simulation(
+                SimulationStage::Spawn,
+                BulletEmitter::tick_emitters.after(Emitter::fire_emitters),
+            )
+            .add_system_to_simulation(SimulationStage::Spawn, spawn_bullets)
+            .add_system_to_simulation(
+                SimulationStage::Spawn,
+                fire_patterns
+                    .after(spawn_bullets)
+                    .after(BulletEmitter::tick_emitters),
+            )
+            .add_system_to_simulation(SimulationStage::Tick, BulletPool::tick_pools)
+            .add_system_to_simulation(
+                SimulationStage::Tick,
+                BulletGrid::rebuild.after(BulletPool::tick_pools),
+            )
+            .add_system_to_simulation(
+                SimulationStage::Tick,
+                BulletPool::collide_player.after(BulletGrid::rebuild),
+            );
+    }
+}
+
+/// The area bullets live in. Bullets further than `margin` outside of it are killed
+/// while they move away from it, so they can still be fired inward from off-screen.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct Playfield {
+    pub rect: Rect,
+    pub margin: f32,
+}
+
+impl Default for Playfield {
+    fn default() -> Self {
+        Self {
+            rect: Rect::from_center_size(Vec2::ZERO, Vec2::new(960., 540.)),
+            margin: 32.,
+        }
+    }
+}
+
+impl Playfield {
+    pub fn contains(&self, point: Vec2) -> bool {
+        let min = self.rect.min - self.margin;
+        let max = self.rect.max + self.margin;
+        point.cmpge(min).all() && point.cmple(max).all()
+    }
+
+    /// How far `point` is outside of the playfield and its margin, 0 when it's in.
+    pub fn distance_outside(&self, point: Vec2) -> f32 {
+        let min = self.rect.min - self.margin;
+        let max = self.rect.max + self.margin;
+        (min - point).max(point - max).max(Vec2::ZERO).length()
+    }
+}
+
+/// Sent for every bullet that touches the player, unless they're invulnerable. The bullet is removed.
+#[derive(Clone, Debug)]
+pub struct PlayerHit {
+    pub bullet_type: String,
+    pub position: Vec2,
+}
+
+/// Sent the first time a bullet comes within [`GrazeRadius`] of the player without hitting them.
+#[derive(Clone, Debug)]
+pub struct Graze {
+    pub bullet_type: String,
+    pub position: Vec2,
+}
+
+enum Contact {
+    Hit(Vec2),
+    Graze(Vec2),
+}
+
+/// How close a bullet has to get to the player to count as a graze.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct GrazeRadius(pub f32);
+
+impl Default for GrazeRadius {
+    fn default() -> Self {
+        Self(24.)
+    }
+}
+
+/// Which pattern to fire: either its name in the [`PatternDatabase`] or a handle to it.
+#[derive(Clone, Debug)]
+pub enum PatternSource {
+    Name(String),
+    Handle(Handle<Pattern>),
+}
+
+impl From<&str> for PatternSource {
+    fn from(name: &str) -> Self {
+        PatternSource::Name(name.into())
+    }
+}
+
+impl From<String> for PatternSource {
+    fn from(name: String) -> Self {
+        PatternSource::Name(name)
+    }
+}
+
+impl From<Handle<Pattern>> for PatternSource {
+    fn from(handle: Handle<Pattern>) -> Self {
+        PatternSource::Handle(handle)
+    }
+}
+
+/// Send this event to fire a pattern.
+/// `rotation` is in radians, with 0 facing right.
+#[derive(Clone, Debug)]
+pub struct FirePattern {
+    pub pattern: PatternSource,
+    pub origin: Vec2,
+    pub rotation: f32,
+    pub params: PatternParams,
+    /// Entity the pattern is fired for, to find it through `PatternInstances::fired_by`
+    pub owner: Option<Entity>,
+}
+
+impl FirePattern {
+    pub fn new(pattern: impl Into<PatternSource>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            origin: Vec2::ZERO,
+            rotation: 0.,
+            params: PatternParams::default(),
+            owner: None,
+        }
+    }
+
+    pub fn at(mut self, origin: Vec2) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn rotated(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_params(mut self, params: PatternParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn fired_by(mut self, owner: Entity) -> Self {
+        self.owner = Some(owner);
+        self
     }
 }
 
-fn spawn_bullets(
-    commands: Commands,
-    asset_server: Res<AssetServer>,
+fn spawn_bullets(input: Res<StepInput>, mut fire_events: EventWriter<FirePattern>) {
+    if let Some(pattern) = &input.fire {
+        fire_events.send(FirePattern::new(pattern.as_str()));
+    }
+}
+
+fn fire_patterns(
+    mut commands: Commands,
     patterns: Res<Assets<Pattern>>,
-    // query_pool: Query<&mut BulletPool>,
-    // bullet_pools: ResMut<BulletPools>,
     pattern_db: Res<PatternDatabase>,
-    editor_state: Res<EditorState>,
-    input: Res<Input<KeyCode>>,
+    mut fire_events: EventReader<FirePattern>,
+    mut rng: ResMut<SimulationRng>,
+    player_query: Query<&Transform, With<Player>>,
 ) {
-    if !input.just_pressed(KeyCode::E) {
-        return;
-    }
+    let player = player_query
+        .get_single()
+        .ok()
+        .map(|player_tr| player_tr.translation.truncate());
+
+    for event in fire_events.iter() {
+        let handle = match &event.pattern {
+            PatternSource::Name(name) => pattern_db.get(name),
+            PatternSource::Handle(handle) => Some(handle.clone()),
+        };
 
-    let pattern = patterns.get(&pattern_db.get(&editor_state.selected_pattern).unwrap());
-    if let Some(pattern) = pattern {
-        pattern.fire(commands, asset_server /* , query_pool, bullet_pools */);
+        let Some(pattern) = handle.and_then(|handle| patterns.get(&handle)) else {
+            warn!("Tried to fire {:?}, which isn't loaded", event.pattern);
+            continue;
+        };
+
+        let instance = pattern.fire_at(
+            &mut commands,
+            Transform::from_translation(event.origin.extend(0.))
+                .with_rotation(Quat::from_rotation_z(event.rotation)),
+            &event.params,
+            player,
+            rng.next_u64(),
+        );
+        if let Some(owner) = event.owner {
+            commands.entity(instance.0).insert(FiredBy(owner));
+        }
     }
 }
 
+/// Every bullet of one type, stored in slots that are reused once their bullet dies.
+/// There is a single pool per bullet type, found through [`BulletPools`].
 #[derive(Component, Clone)]
 pub struct BulletPool {
     states: Vec<Vec3A>,
+    /// States before the last tick, for swept collisions
+    previous: Vec<Vec3A>,
     speeds: Vec<f32>,
     angulars: Vec<f32>,
+    variables: Vec<BulletVariables>,
+    /// Pool age at which each bullet was added
+    spawn_times: Vec<f64>,
+    lifetimes: Vec<f32>,
+    alive: Vec<bool>,
+    grazed: Vec<bool>,
+    modifiers: Vec<Arc<[BulletModifier]>>,
+    params: Vec<Arc<PatternParams>>,
+    instances: Vec<Entity>,
 
-    modifiers: Vec<BulletModifier>,
+    bullet_type: String,
+    definition: BulletType,
+    /// Where `definition` comes from, if the type has a definition file
+    definition_handle: Option<Handle<BulletType>>,
     handle: Handle<Image>,
-    index: usize,
-    capacity: usize,
-    lifetime: f32,
-    age: f32,
+    /// Dead slots, ready to be claimed by new bullets
+    free: Vec<usize>,
+    alive_count: usize,
+    /// Seconds since the pool was created. Kept in double precision, as a pool lives as long as the game
+    age: f64,
 }
 
 impl BulletPool {
-    fn new(capacity: usize, lifetime: f32, handle: Handle<Image>) -> Self {
+    fn new(
+        bullet_type: String,
+        definition: BulletType,
+        definition_handle: Option<Handle<BulletType>>,
+        handle: Handle<Image>,
+    ) -> Self {
         Self {
-            states: vec![Vec3A::new(f32::MAX, 0., 0.); capacity],
-            // velocities: vec![Vec4::ZERO; capacity],
-            speeds: vec![0.; capacity],
-            angulars: vec![0.; capacity],
-
+            states: Default::default(),
+            previous: Default::default(),
+            speeds: Default::default(),
+            angulars: Default::default(),
+            variables: Default::default(),
+            spawn_times: Default::default(),
+            lifetimes: Default::default(),
+            alive: Default::default(),
+            grazed: Default::default(),
             modifiers: Default::default(),
-            index: 0,
-            age: 0.0,
-            capacity,
+            params: Default::default(),
+            instances: Default::default(),
+
+            bullet_type,
+            definition,
+            definition_handle,
             handle,
-            lifetime,
+            free: Default::default(),
+            alive_count: 0,
+            age: 0.0,
         }
     }
 
-    pub fn add_modifier(&mut self, modifier: BulletModifier) {
-        self.modifiers.push(modifier);
-    }
-
-    pub fn add(&mut self, position: Vec2, rotation: f32, speed: f32, angular: f32) {
-        let i = self.index;
+    /// Adds a bullet to a free slot, growing the pool if there is none.
+    fn add(&mut self, bullet: &NewBullet, spawn: &SpawnBullets) {
+        let state = Vec3A::new(bullet.position.x, bullet.position.y, bullet.rotation);
 
-        self.states[i] = Vec3A::new(position.x, position.y, rotation);
-        self.speeds[i] = speed;
-        self.angulars[i] = angular;
+        if let Some(i) = self.free.pop() {
+            self.states[i] = state;
+            self.previous[i] = state;
+            self.speeds[i] = bullet.speed;
+            self.angulars[i] = bullet.angular;
+            self.variables[i] = bullet.variables;
+            self.spawn_times[i] = self.age;
+            self.lifetimes[i] = bullet.lifetime;
+            self.alive[i] = true;
+            self.grazed[i] = false;
+            self.modifiers[i] = spawn.modifiers.clone();
+            self.params[i] = spawn.params.clone();
+            self.instances[i] = spawn.instance;
+        } else {
+            self.states.push(state);
+            self.previous.push(state);
+            self.speeds.push(bullet.speed);
+            self.angulars.push(bullet.angular);
+            self.variables.push(bullet.variables);
+            self.spawn_times.push(self.age);
+            self.lifetimes.push(bullet.lifetime);
+            self.alive.push(true);
+            self.grazed.push(false);
+            self.modifiers.push(spawn.modifiers.clone());
+            self.params.push(spawn.params.clone());
+            self.instances.push(spawn.instance);
+        }
 
-        self.index = (self.index + 1) % self.capacity;
+        self.alive_count += 1;
     }
 
-    fn tick(&mut self, time: &Res<Time>) {
-        let delta_time = time.delta_seconds();
-        self.age += delta_time;
+    fn tick(&mut self, delta_time: f32, playfield: &Playfield) {
+        self.age += delta_time as f64;
+        self.previous.copy_from_slice(&self.states);
 
-        (&mut self.states, &self.speeds, &self.angulars)
+        (&mut self.states, &self.speeds, &self.angulars, &self.alive)
             .into_par_iter()
-            .for_each(|(state, speed, angular)| {
+            .filter(|(_, _, _, alive)| **alive)
+            .for_each(|(state, speed, angular, _)| {
                 *state +=
                     Vec3A::from((Vec2::from_angle(state.z) * *speed).extend(*angular) * delta_time);
-            })
-    }
+            });
 
-    fn check_collisions(&mut self, player_tr: &Transform) {
-        let player_pos = Vec3A::from(player_tr.translation);
-
-        for (i, bullet_pos) in self.states.iter().enumerate() {
-            if player_pos.distance_squared(*bullet_pos * Vec3A::new(1., 1., 0.)) < PLAYER_RADIUS_SQR
+        // Bullets outside of the playfield are only killed when heading further out,
+        // which includes any bullet that has just left it
+        let distance_outside =
+            |state: Vec3A| playfield.distance_outside(Vec2::new(state.x, state.y));
+        for i in 0..self.capacity() {
+            if self.alive[i]
+                && (self.bullet_age(i) >= self.lifetimes[i]
+                    || distance_outside(self.states[i]) > distance_outside(self.previous[i]))
             {
                 self.remove(i);
-                break;
             }
         }
     }
 
+    /// Removes the bullet in slot `i` if it touches the player.
+    /// Bullets that only come within `graze_radius` are grazed instead, once per lifetime.
+    fn check_collision(
+        &mut self,
+        i: usize,
+        player_pos: Vec2,
+        graze_radius: f32,
+    ) -> Option<Contact> {
+        if !self.alive[i] {
+            return None;
+        }
+
+        let state = self.states[i];
+        let bullet_pos = Vec2::new(state.x, state.y);
+        let hitbox = self.definition.hitbox;
+        let previous = self.previous[i];
+        let swept = self.definition.swept;
+        let overlaps = |radius| {
+            if swept {
+                hitbox.overlaps_circle_swept(previous, state, player_pos, radius)
+            } else {
+                hitbox.overlaps_circle(state, player_pos, radius)
+            }
+        };
+
+        if overlaps(PLAYER_RADIUS) {
+            self.remove(i);
+            Some(Contact::Hit(bullet_pos))
+        } else if !self.grazed[i] && overlaps(graze_radius) {
+            self.grazed[i] = true;
+            Some(Contact::Graze(bullet_pos))
+        } else {
+            None
+        }
+    }
+
     fn tick_modifiers(&mut self) {
-        let mut params = StrToF64Namespace::new();
-        params.insert("t", self.age as f64);
+        for i in 0..self.capacity() {
+            if !self.alive[i] {
+                continue;
+            }
 
-        for modifier in self.modifiers.iter() {
-            let value = modifier.expression.eval(&mut params);
-            for i in modifier.range.clone() {
+            for modifier in self.modifiers[i].iter() {
+                let value = modifier.expression.eval_with(
+                    &self.variables[i],
+                    &self.params[i],
+                    self.bullet_age(i),
+                );
                 match modifier.property {
-                    ModifierProperty::Speed => self.speeds[i] = value,
+                    ModifierProperty::Speed => {
+                        self.speeds[i] = value + self.variables[i].speed_offset
+                    }
                     ModifierProperty::Angular => self.angulars[i] = value,
                 }
             }
@@ -144,47 +435,343 @@ impl BulletPool {
 
     fn tick_pools(
         mut pool_query: Query<&mut BulletPool>,
-        player_query: Query<&Transform, With<Player>>,
-        time: Res<Time>,
+        clock: Res<SimulationClock>,
+        playfield: Res<Playfield>,
     ) {
         pool_query.par_for_each_mut(4, |mut bullet_pool| {
-            bullet_pool.tick(&time);
-            bullet_pool.check_collisions(player_query.single());
-
+            bullet_pool.tick(clock.step, &playfield);
             bullet_pool.tick_modifiers();
         });
     }
 
-    fn free_pools(mut commands: Commands, pool_query: Query<(Entity, &BulletPool)>) {
-        for (entity, _) in pool_query
-            .iter()
-            .filter(|(_, pool)| pool.age >= pool.lifetime)
-        {
-            commands.entity(entity).despawn();
+    fn collide_player(
+        mut pool_query: Query<&mut BulletPool>,
+        player_query: Query<(&Transform, Option<&Lives>), With<Player>>,
+        grid: Res<BulletGrid>,
+        graze_radius: Res<GrazeRadius>,
+        mut hit_events: EventWriter<PlayerHit>,
+        mut graze_events: EventWriter<Graze>,
+    ) {
+        let Ok((player_tr, lives)) = player_query.get_single() else { return };
+        // Invulnerable players pass through bullets, without hitting or grazing them
+        if lives.map_or(false, Lives::is_invulnerable) {
+            return;
+        }
+        let player_pos = player_tr.translation.truncate();
+
+        let reach = PLAYER_RADIUS.max(graze_radius.0);
+        for bullet in grid.query_circle(player_pos, reach) {
+            let Ok(mut bullet_pool) = pool_query.get_mut(bullet.pool) else { continue };
+            match bullet_pool.check_collision(bullet.slot, player_pos, graze_radius.0) {
+                Some(Contact::Hit(position)) => hit_events.send(PlayerHit {
+                    bullet_type: bullet_pool.bullet_type.clone(),
+                    position,
+                }),
+                Some(Contact::Graze(position)) => graze_events.send(Graze {
+                    bullet_type: bullet_pool.bullet_type.clone(),
+                    position,
+                }),
+                None => {}
+            }
+        }
+    }
+
+    /// Picks up definition files as they change on disk.
+    /// The simulation waits for every definition to load before its first step,
+    /// so only hot reloading depends on frame timing.
+    fn apply_definitions(
+        mut pool_query: Query<&mut BulletPool>,
+        definitions: Res<Assets<BulletType>>,
+        mut definition_events: EventReader<AssetEvent<BulletType>>,
+        asset_server: Res<AssetServer>,
+        images: Option<Res<Assets<Image>>>,
+    ) {
+        for event in definition_events.iter() {
+            let handle = match event {
+                AssetEvent::Created { handle } | AssetEvent::Modified { handle } => handle,
+                AssetEvent::Removed { .. } => continue,
+            };
+            let Some(definition) = definitions.get(handle) else { continue };
+
+            for mut pool in pool_query.iter_mut() {
+                if pool.definition_handle.as_ref() == Some(handle) {
+                    if images.is_some() {
+                        pool.handle = asset_server.load(definition.sprite.as_str());
+                    }
+                    pool.definition = definition.clone();
+                }
+            }
         }
     }
 
+    /// Moves the bullets fired by `instance` by `offset`, leaving their heading alone.
+    pub fn translate(&mut self, instance: Entity, offset: Vec2) {
+        for i in 0..self.capacity() {
+            if self.is_of(i, instance) {
+                self.states[i] += Vec3A::from(offset.extend(0.));
+                self.previous[i] += Vec3A::from(offset.extend(0.));
+            }
+        }
+    }
+
+    /// Rotates the bullets fired by `instance` by `angle` radians around `pivot`,
+    /// turning their heading with them.
+    pub fn rotate(&mut self, instance: Entity, angle: f32, pivot: Vec2) {
+        let rotation = Vec2::from_angle(angle);
+        for i in 0..self.capacity() {
+            if self.is_of(i, instance) {
+                for state in [&mut self.states[i], &mut self.previous[i]] {
+                    let position = pivot + rotation.rotate(Vec2::new(state.x, state.y) - pivot);
+                    *state = Vec3A::new(position.x, position.y, state.z + angle);
+                }
+            }
+        }
+    }
+
+    /// Kills every bullet fired by `instance`.
+    pub fn clear_instance(&mut self, instance: Entity) {
+        for i in 0..self.capacity() {
+            if self.is_of(i, instance) {
+                self.remove(i);
+            }
+        }
+    }
+
+    /// Number of bullets fired by `instance` that are still alive.
+    pub fn instance_len(&self, instance: Entity) -> usize {
+        (0..self.capacity())
+            .filter(|i| self.is_of(*i, instance))
+            .count()
+    }
+
+    /// Instances that still have bullets in this pool.
+    pub fn live_instances(&self) -> impl Iterator<Item = Entity> + '_ {
+        (0..self.capacity())
+            .filter(|i| self.alive[*i])
+            .map(|i| self.instances[i])
+    }
+
+    /// Whether slot `i` holds a live bullet fired by `instance`.
+    fn is_of(&self, i: usize, instance: Entity) -> bool {
+        self.alive[i] && self.instances[i] == instance
+    }
+
+    pub fn bullet_type(&self) -> &str {
+        &self.bullet_type
+    }
+
+    pub fn definition(&self) -> &BulletType {
+        &self.definition
+    }
+
+    /// Number of bullets that are still alive.
     pub fn len(&self) -> usize {
-        self.capacity
+        self.alive_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alive_count == 0
+    }
+
+    /// Number of slots allocated, alive or not.
+    pub fn capacity(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Position and rotation of the bullet in `slot`, like the ones [`BulletGrid`] queries return.
+    pub fn state(&self, slot: usize) -> Option<Vec3A> {
+        self.alive
+            .get(slot)
+            .filter(|alive| **alive)
+            .map(|_| self.states[slot])
+    }
+
+    /// Positions and rotations of the bullets that are still alive.
+    pub fn live_states(&self) -> impl Iterator<Item = &Vec3A> {
+        self.states
+            .iter()
+            .zip(self.alive.iter())
+            .filter(|(_, alive)| **alive)
+            .map(|(state, _)| state)
+    }
+
+    /// Like [`BulletPool::live_states`], but `overstep` of the way from the previous step's states,
+    /// to draw bullets between steps.
+    pub fn live_interpolated_states(&self, overstep: f32) -> impl Iterator<Item = Vec3A> + '_ {
+        self.states
+            .iter()
+            .zip(self.previous.iter())
+            .zip(self.alive.iter())
+            .filter(|(_, alive)| **alive)
+            .map(move |((state, previous), _)| previous.lerp(*state, overstep))
+    }
+
+    /// Ages of the bullets that are still alive, in the same order as [`BulletPool::live_states`].
+    pub fn live_ages(&self) -> impl Iterator<Item = f32> + '_ {
+        (0..self.capacity())
+            .filter(|i| self.alive[*i])
+            .map(|i| self.bullet_age(i))
+    }
+
+    fn bullet_age(&self, i: usize) -> f32 {
+        (self.age - self.spawn_times[i]) as f32
     }
 
     fn remove(&mut self, i: usize) {
-        self.states[i].x = f32::MAX;
+        if self.alive[i] {
+            self.alive[i] = false;
+            self.alive_count -= 1;
+            self.free.push(i);
+        }
     }
 }
 
+/// The pool entity of each bullet type that has been fired so far.
 #[derive(Resource, Default)]
 pub struct BulletPools(BTreeMap<String, Entity>);
 
+impl BulletPools {
+    pub fn get(&self, bullet_type: &str) -> Option<Entity> {
+        self.0.get(bullet_type).copied()
+    }
+}
+
 #[derive(Clone)]
 pub struct BulletModifier {
-    range: Range<usize>,
-    expression: Arc<ExpressionSlab>,
-    property: ModifierProperty,
+    pub(crate) expression: Arc<ExpressionSlab>,
+    pub(crate) property: ModifierProperty,
 }
 
 #[derive(Clone)]
-enum ModifierProperty {
+pub(crate) enum ModifierProperty {
     Speed,
     Angular,
 }
+
+/// A bullet about to be added to its pool by [`SpawnBullets`].
+pub(crate) struct NewBullet {
+    pub position: Vec2,
+    pub rotation: f32,
+    pub speed: f32,
+    pub angular: f32,
+    pub lifetime: f32,
+    pub variables: BulletVariables,
+}
+
+/// Adds bullets of a single type and firing to that type's pool, creating the pool if needed.
+pub(crate) struct SpawnBullets {
+    pub bullet_type: String,
+    pub bullets: Vec<NewBullet>,
+    pub modifiers: Arc<[BulletModifier]>,
+    pub params: Arc<PatternParams>,
+    pub instance: Entity,
+}
+
+impl Command for SpawnBullets {
+    fn write(self, world: &mut World) {
+        let existing = world.resource::<BulletPools>().get(&self.bullet_type);
+        let pool_entity = match existing {
+            Some(entity) => entity,
+            None => {
+                // Definitions that haven't loaded yet get applied once they do
+                let definition_handle = world.resource::<BulletTypes>().get(&self.bullet_type);
+                let definition = definition_handle
+                    .as_ref()
+                    .and_then(|handle| world.resource::<Assets<BulletType>>().get(handle))
+                    .cloned()
+                    .unwrap_or_else(|| BulletType::fallback(&self.bullet_type));
+
+                // Apps without rendering have no images to load sprites into
+                let handle = if world.contains_resource::<Assets<Image>>() {
+                    world
+                        .resource::<AssetServer>()
+                        .load(definition.sprite.as_str())
+                } else {
+                    Handle::default()
+                };
+                let entity = world
+                    .spawn(BulletPool::new(
+                        self.bullet_type.clone(),
+                        definition,
+                        definition_handle,
+                        handle,
+                    ))
+                    .id();
+                world
+                    .resource_mut::<BulletPools>()
+                    .0
+                    .insert(self.bullet_type.clone(), entity);
+                entity
+            }
+        };
+
+        let mut pool = world.get_mut::<BulletPool>(pool_entity).unwrap();
+        for bullet in self.bullets.iter() {
+            pool.add(bullet, &self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+    use crate::{bullet::pattern::PatternInstances, headless::HeadlessSimulation};
+
+    #[test]
+    fn bullets_die_when_moving_out_of_the_playfield() {
+        let mut pool = BulletPool::new("test".into(), BulletType::new("test.png"), None, default());
+        let spawn = SpawnBullets {
+            bullet_type: "test".into(),
+            bullets: vec![],
+            modifiers: Arc::new([]),
+            params: Arc::default(),
+            instance: Entity::from_raw(0),
+        };
+        // The default playfield, with its margin, ends at x = 512
+        for (x, rotation) in [(600., PI), (600., 0.), (500., 0.), (500., PI)] {
+            let bullet = NewBullet {
+                position: Vec2::new(x, 0.),
+                rotation,
+                speed: 60.,
+                angular: 0.,
+                lifetime: f32::INFINITY,
+                variables: BulletVariables::default(),
+            };
+            pool.add(&bullet, &spawn);
+        }
+
+        let playfield = Playfield::default();
+        for _ in 0..30 {
+            pool.tick(1. / 60., &playfield);
+        }
+        assert_eq!(pool.alive, [true, false, false, true]);
+    }
+
+    #[test]
+    fn fired_patterns_can_be_found_by_owner() {
+        let mut simulation = HeadlessSimulation::new(60.);
+        let pattern = simulation
+            .load_pattern("patterns/pattern1.pattern.json")
+            .unwrap();
+        let owner = simulation.app.world.spawn_empty().id();
+        simulation.fire(FirePattern::new(pattern.clone()).fired_by(owner));
+        simulation.fire(FirePattern::new(pattern));
+        simulation.step();
+
+        let mut state = SystemState::<PatternInstances>::new(&mut simulation.app.world);
+        let mut instances = state.get_mut(&mut simulation.app.world);
+        let fired = instances.fired_by(owner);
+        assert_eq!(fired.len(), 1);
+        assert_eq!(instances.alive(fired[0]), 40);
+
+        instances.cancel_fired_by(owner);
+        state.apply(&mut simulation.app.world);
+        simulation.step();
+        let live: usize = simulation.pools().iter().map(|pool| pool.len()).sum();
+        assert_eq!(live, 40);
+    }
+}
@@ -1,19 +1,29 @@
 use std::collections::BTreeMap;
-use std::f32::consts::PI;
+use std::f32::consts::{PI, TAU};
+use std::fmt;
 use std::path::Path;
 use std::str::from_utf8;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 use bevy::prelude::*;
 use bevy::{
     asset::{AssetLoader, LoadContext, LoadedAsset},
+    ecs::system::SystemParam,
     reflect::TypeUuid,
-    utils::BoxedFuture,
+    utils::{BoxedFuture, HashSet},
 };
 use fasteval::*;
 use serde_json::Value;
 
-use super::{BulletModifier, BulletPool};
+use super::{BulletModifier, BulletPool, ModifierProperty, NewBullet, SpawnBullets};
+use crate::{
+    player::Player,
+    random::{combine, hash_str, RandomStream},
+    simulation::{SimulationAssets, SimulationClock},
+};
 
 #[derive(Default)]
 pub struct PatternLoader;
@@ -26,7 +36,7 @@ impl AssetLoader for PatternLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
         Box::pin(async move {
-            let pattern = parse(from_utf8(bytes)?);
+            let pattern = parse(from_utf8(bytes)?)?;
             load_context.set_default_asset(LoadedAsset::new(pattern));
             Ok(())
         })
@@ -45,6 +55,7 @@ impl PatternLoader {
     pub(crate) fn init_database(
         asset_server: Res<AssetServer>,
         mut patterns: ResMut<PatternDatabase>,
+        mut simulation_assets: ResMut<SimulationAssets>,
     ) {
         if let Ok(scripts_iter) = asset_server
             .asset_io()
@@ -56,6 +67,7 @@ impl PatternLoader {
                 }
 
                 let handle = asset_server.load(path.clone());
+                simulation_assets.add(&handle);
                 patterns.0.insert(
                     path.file_stem()
                         .map(|u| u.to_str().unwrap().split('.').next().unwrap())
@@ -70,18 +82,20 @@ impl PatternLoader {
 
 #[derive(Component, Clone, Debug)]
 pub struct BulletContext {
-    lifetime: f32,
+    lifetime: Arc<ExpressionSlab>,
     position: Vec2,
     rotation: f32,
     angular_velocity: Arc<ExpressionSlab>,
     speed: Arc<ExpressionSlab>,
     id: String,
+    variables: BulletVariables,
+    params: Arc<PatternParams>,
 }
 
 impl Default for BulletContext {
     fn default() -> Self {
         Self {
-            lifetime: 10.,
+            lifetime: Arc::new(ExpressionSlab::constant(10.)),
             speed: Arc::new(ExpressionSlab::new(
                 fasteval::Instruction::IConst(0.),
                 Slab::default(),
@@ -93,15 +107,87 @@ impl Default for BulletContext {
             position: Vec2::default(),
             rotation: f32::default(),
             id: "bullet".into(),
+            variables: BulletVariables::default(),
+            params: Arc::default(),
         }
     }
 }
 
 impl BulletContext {
-    pub fn new(speed: f32) -> Self {
+    /// Evaluates an expression with this bullet's variables, at the moment it is spawned.
+    fn eval(&self, expression: &ExpressionSlab) -> f32 {
+        expression.eval_with(&self.variables, &self.params, 0.)
+    }
+
+    /// Evaluates a time in seconds, where negative and NaN results count as 0.
+    fn eval_duration(&self, expression: &ExpressionSlab) -> f32 {
+        self.eval(expression).max(0.)
+    }
+}
+
+/// Named values passed in when firing a pattern, readable from its expressions.
+/// A pattern file declares the ones it uses, with their defaults, in its root `params` object.
+pub type PatternParams = BTreeMap<String, f64>;
+
+/// Per-bullet values set by the operations a bullet went through, mostly exposed to pattern expressions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BulletVariables {
+    /// Index within the innermost ring or arc, and that ring or arc's bullet count
+    i: f64,
+    n: f64,
+    ring_i: f64,
+    ring_n: f64,
+    arc_i: f64,
+    arc_n: f64,
+    repeat_i: f64,
+    repeat_n: f64,
+    /// Angle to the player in degrees: from the pattern's origin when it was fired,
+    /// or from the bullet when it last went through an `aim`, which sees where the player is now
+    player_angle: f64,
+    /// Seeds the random functions, from the seed the pattern was fired with and the bullet's indices
+    seed: u64,
+    /// Added to the bullet's speed by `random_spread`
+    pub(crate) speed_offset: f32,
+}
+
+impl BulletVariables {
+    /// Looks up a variable by name. `t` is the age of the bullet.
+    pub fn lookup(&self, name: &str, t: f64) -> Option<f64> {
+        Some(match name {
+            "t" => t,
+            "i" => self.i,
+            "n" => self.n,
+            "ring_i" => self.ring_i,
+            "ring_n" => self.ring_n,
+            "arc_i" => self.arc_i,
+            "arc_n" => self.arc_n,
+            "repeat_i" => self.repeat_i,
+            "repeat_n" => self.repeat_n,
+            "player_angle" => self.player_angle,
+            _ => return None,
+        })
+    }
+
+    /// The random functions, with the numbers of arguments they take.
+    const FUNCTIONS: [(&str, &[usize]); 3] = [("rand", &[0, 2]), ("randn", &[0]), ("noise", &[1])];
+
+    /// Looks up a variable, or calls one of the random functions.
+    fn call(&self, name: &str, args: &[f64], t: f64, random: &mut RandomStream) -> Option<f64> {
+        Some(match (name, args) {
+            ("rand", []) => random.uniform(),
+            ("rand", [min, max]) => random.range(*min, *max),
+            ("randn", []) => random.normal(),
+            ("noise", [x]) => random.noise(*x),
+            (name, []) => return self.lookup(name, t),
+            _ => return None,
+        })
+    }
+
+    /// Variables for the `i`th bullet made out of these ones, with its own seed.
+    fn child(&self, i: u32) -> Self {
         Self {
-            speed: Arc::new(ExpressionSlab::from(speed.to_string().as_str())),
-            ..Default::default()
+            seed: combine(self.seed, i as u64),
+            ..*self
         }
     }
 }
@@ -119,19 +205,44 @@ impl PatternDatabase {
 #[derive(Default, Debug, TypeUuid)]
 #[uuid = "1ff044c3-1d98-4b22-a7e2-73a41298ff98"]
 pub struct Pattern {
-    pub operations: Vec<PatternOp>,
+    pub nodes: Vec<Arc<PatternNode>>,
+    /// Speed given to bullets that don't set their own
+    pub speed: f32,
+    pub params: PatternParams,
+}
+
+/// A single operation in a pattern tree.
+/// Each child receives its own copy of the bullets produced by `operation`.
+#[derive(Debug)]
+pub struct PatternNode {
+    pub operation: PatternOp,
+    pub children: Vec<Arc<PatternNode>>,
 }
 
 impl Pattern {
-    fn ring(bullets: Vec<BulletContext>, count: u32, radius: f32) -> Vec<BulletContext> {
+    fn ring(
+        bullets: Vec<BulletContext>,
+        count: &ExpressionSlab,
+        radius: &ExpressionSlab,
+    ) -> Vec<BulletContext> {
         bullets
             .iter()
             .flat_map(|b| {
-                (0..count).map(|i| {
+                let count = b.eval(count) as u32;
+                let radius = b.eval(radius);
+                (0..count).map(move |i| {
                     let rotation = b.rotation + i as f32 / count as f32 * 2. * PI;
+                    let (i, n) = (i as f64, count as f64);
                     BulletContext {
                         position: b.position + Vec2::from_angle(rotation) * radius,
                         rotation,
+                        variables: BulletVariables {
+                            i,
+                            n,
+                            ring_i: i,
+                            ring_n: n,
+                            ..b.variables.child(i as u32)
+                        },
                         ..b.clone()
                     }
                 })
@@ -139,200 +250,853 @@ impl Pattern {
             .collect()
     }
 
-    fn arc(bullets: Vec<BulletContext>, count: u32, angle: f32) -> Vec<BulletContext> {
-        if count == 1 {
-            return bullets;
-        }
-
-        let step = angle / (count as f32 - 1.0);
+    fn arc(
+        bullets: Vec<BulletContext>,
+        count: &ExpressionSlab,
+        angle: &ExpressionSlab,
+    ) -> Vec<BulletContext> {
         bullets
             .iter()
             .flat_map(|b| {
-                (0..count).map(|i| BulletContext {
-                    rotation: b.rotation - angle / 2.0 + step * i as f32,
+                let count = b.eval(count) as u32;
+                let angle = b.eval(angle).to_radians();
+
+                // A single bullet goes straight ahead
+                let (step, offset) = if count > 1 {
+                    (angle / (count as f32 - 1.0), angle / 2.0)
+                } else {
+                    (0., 0.)
+                };
+                let n = count as f64;
+
+                (0..count).map(move |i| BulletContext {
+                    rotation: b.rotation - offset + step * i as f32,
+                    variables: BulletVariables {
+                        i: i as f64,
+                        n,
+                        arc_i: i as f64,
+                        arc_n: n,
+                        ..b.variables.child(i)
+                    },
                     ..b.clone()
                 })
             })
             .collect()
     }
 
-    pub fn fire(
+    /// Fires the pattern from `origin`'s position, facing along its local X axis.
+    /// `params` override the defaults declared by the pattern file,
+    /// and `aim` operations target `player` if there is one.
+    /// `seed` drives the random functions and `random_spread`: firing with the same seed fires the same bullets.
+    ///
+    /// The returned id can be used with [`PatternInstances`] to control the fired bullets.
+    pub fn fire_at(
         &self,
+        commands: &mut Commands,
+        origin: Transform,
+        params: &PatternParams,
+        player: Option<Vec2>,
+        seed: u64,
+    ) -> PatternInstanceId {
+        let mut merged_params = self.params.clone();
+        merged_params.extend(params.iter().map(|(name, value)| (name.clone(), *value)));
+
+        let position = origin.translation.truncate();
+        let facing = origin.rotation * Vec3::X;
+
+        let mut bullet = BulletContext {
+            position,
+            rotation: facing.y.atan2(facing.x),
+            params: Arc::new(merged_params),
+            ..Default::default()
+        };
+        bullet.variables.player_angle = player
+            .map(|player| Pattern::angle_to(position, player).to_degrees() as f64)
+            .unwrap_or_default();
+        bullet.variables.seed = seed;
+
+        let instance = commands.spawn_empty().id();
+        let bullets = vec![bullet];
+        let mut executor = PatternExecutor::new(commands, instance, player, 0.);
+
+        for node in self.nodes.iter() {
+            executor.run(node, bullets.clone(), 0.);
+        }
+
+        let tasks = executor.pending;
+        commands
+            .entity(instance)
+            .insert(PatternInstance { age: 0., tasks });
+
+        PatternInstanceId(instance)
+    }
+
+    /// Rotates every bullet towards `player`, plus an offset in degrees.
+    /// Without a player, only the offset is applied.
+    ///
+    /// The player may have moved since the pattern was fired, so `player_angle` is updated
+    /// to the angle aimed at, before the offset. The offset expression already sees the new value.
+    fn aim(
+        bullets: Vec<BulletContext>,
+        offset: &ExpressionSlab,
+        player: Option<Vec2>,
+    ) -> Vec<BulletContext> {
+        bullets
+            .into_iter()
+            .map(|mut b| {
+                let rotation = match player {
+                    Some(player) => {
+                        let angle = Pattern::angle_to(b.position, player);
+                        b.variables.player_angle = angle.to_degrees() as f64;
+                        angle
+                    }
+                    None => b.rotation,
+                };
+                let offset = b.eval(offset).to_radians();
+
+                BulletContext {
+                    rotation: rotation + offset,
+                    ..b
+                }
+            })
+            .collect()
+    }
+
+    /// Turns, speeds up or slows down, and moves every bullet by a random amount,
+    /// up to an angle in degrees, a speed and a distance.
+    fn random_spread(
+        bullets: Vec<BulletContext>,
+        rotation: &ExpressionSlab,
+        speed: &ExpressionSlab,
+        distance: &ExpressionSlab,
+        salt: u64,
+    ) -> Vec<BulletContext> {
+        bullets
+            .into_iter()
+            .map(|b| {
+                let rotation = b.eval(rotation).to_radians() as f64;
+                let speed = b.eval(speed) as f64;
+                let distance = b.eval(distance);
+
+                let mut random = RandomStream::new(combine(b.variables.seed, salt));
+                let rotation = random.range(-rotation, rotation) as f32;
+                let speed = random.range(-speed, speed) as f32;
+                // Uniform over the disk rather than bunched up in its middle
+                let offset = Vec2::from_angle(random.range(0., TAU as f64) as f32)
+                    * distance
+                    * random.uniform().sqrt() as f32;
+
+                BulletContext {
+                    position: b.position + offset,
+                    rotation: b.rotation + rotation,
+                    variables: BulletVariables {
+                        speed_offset: b.variables.speed_offset + speed,
+                        ..b.variables
+                    },
+                    ..b
+                }
+            })
+            .collect()
+    }
+
+    fn angle_to(from: Vec2, to: Vec2) -> f32 {
+        let direction = to - from;
+        direction.y.atan2(direction.x)
+    }
+
+    fn spawn_bullets(
+        bullet: &BulletContext,
+        bullets: &[BulletContext],
+        commands: &mut Commands,
+        instance: Entity,
+    ) {
+        let mut modifiers = vec![];
+        if !matches!(bullet.speed.expression, IConst(_)) {
+            modifiers.push(BulletModifier {
+                expression: bullet.speed.clone(),
+                property: ModifierProperty::Speed,
+            });
+        }
+        if !matches!(bullet.angular_velocity.expression, IConst(_)) {
+            modifiers.push(BulletModifier {
+                expression: bullet.angular_velocity.clone(),
+                property: ModifierProperty::Angular,
+            });
+        }
+
+        commands.add(SpawnBullets {
+            bullet_type: bullet.id.clone(),
+            bullets: bullets
+                .iter()
+                .map(|iter_bullet| NewBullet {
+                    position: iter_bullet.position,
+                    rotation: iter_bullet.rotation,
+                    speed: iter_bullet.eval(&bullet.speed) + iter_bullet.variables.speed_offset,
+                    angular: iter_bullet.eval(&bullet.angular_velocity),
+                    lifetime: iter_bullet.eval(&bullet.lifetime),
+                    variables: iter_bullet.variables,
+                })
+                .collect(),
+            modifiers: modifiers.into(),
+            params: bullets
+                .first()
+                .map(|b| b.params.clone())
+                .unwrap_or_default(),
+            instance,
+        });
+    }
+}
+
+impl PatternNode {
+    /// How long this node keeps emitting after it starts, in seconds.
+    fn duration(&self, bullet: &BulletContext) -> f32 {
+        let children = self.children.iter().map(|c| c.duration(bullet));
+
+        match &self.operation {
+            PatternOp::Repeat(count, interval) => match bullet.eval(count) as u32 {
+                0 => 0.,
+                count => {
+                    (count - 1) as f32 * bullet.eval_duration(interval)
+                        + children.fold(0., f32::max)
+                }
+            },
+            PatternOp::Wait(duration) => {
+                bullet.eval_duration(duration) + children.fold(0., f32::max)
+            }
+            PatternOp::Sequence => children.sum(),
+            _ => children.fold(0., f32::max),
+        }
+    }
+}
+
+/// A part of a pattern that is scheduled to run at a later time.
+struct PatternTask {
+    time: f32,
+    node: Arc<PatternNode>,
+    bullets: Vec<BulletContext>,
+}
+
+/// Identifies the bullets fired by a single [`Pattern::fire_at`] call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PatternInstanceId(pub Entity);
+
+/// Links a fired pattern to the entity that fired it,
+/// so that [`PatternInstances::cancel_fired_by`] can clear it.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct FiredBy(pub Entity);
+
+/// A fired pattern, along with the operations it still has scheduled to run.
+/// Despawned once every scheduled operation has run and all of its bullets are gone.
+#[derive(Component)]
+pub struct PatternInstance {
+    age: f32,
+    tasks: Vec<PatternTask>,
+}
+
+impl PatternInstance {
+    pub(crate) fn tick_instances(
         mut commands: Commands,
-        asset_server: Res<AssetServer>,
-        // mut pool_query: Query<&mut BulletPool>,
-        // mut bullet_pools: ResMut<BulletPools>,
+        clock: Res<SimulationClock>,
+        mut instance_query: Query<(Entity, &mut PatternInstance)>,
+        pool_query: Query<&BulletPool>,
+        player_query: Query<&Transform, With<Player>>,
     ) {
-        let mut bullets = vec![BulletContext::new(60.)];
-
-        for op in self.operations.iter() {
-            bullets = match op {
-                PatternOp::Ring(count, radius) => Pattern::ring(
-                    bullets,
-                    count.eval(&mut StrToF64Namespace::from([("t", 0.0)])) as u32,
-                    *radius,
-                ),
-                PatternOp::Arc(count, angle) => Pattern::arc(bullets, *count, *angle),
-                PatternOp::Bullet(bullet) => {
-                    /* et mut bullet_pool = if !bullet_pools.0.contains_key(&bullet.id) {
-
-                        // BulletPool::create_pool(&mut commands, &asset_server, &bullet.id);
-                        bullet_pools
-                            .0
-                            .insert(bullet.id.clone(), commands.spawn(bullet_pool).id());
-                        &mut bullet_pool
-                    } else {
-                        pool_query
-                            .get_mut(*bullet_pools.0.get(&bullet.id).unwrap())
-                            .unwrap()
-                            .as_mut()
-                    }; */
-
-                    let mut bullet_pool = BulletPool::new(
-                        bullets.len(),
-                        bullet.lifetime,
-                        asset_server.load(format!("bullets/{}.png", bullet.id)),
-                    );
-
-                    let default_speed = bullet
-                        .speed
-                        .clone()
-                        .eval(&mut StrToF64Namespace::from([("t", 0.0)]));
-                    let default_angular = bullet
-                        .angular_velocity
-                        .clone()
-                        .eval(&mut StrToF64Namespace::from([("t", 0.0)]));
-
-                    bullets.iter().for_each(|iter_bullet| {
-                        bullet_pool.add(
-                            iter_bullet.position,
-                            iter_bullet.rotation,
-                            default_speed,
-                            default_angular,
-                        );
-                    });
-
-                    match bullet.speed.expression {
-                        IConst(_) => {}
-                        _ => {
-                            bullet_pool.add_modifier(BulletModifier {
-                                range: 0..bullets.len(),
-                                expression: bullet.speed.clone(),
-                                property: super::ModifierProperty::Speed,
-                            });
-                        }
-                    }
+        let player = player_query
+            .get_single()
+            .ok()
+            .map(|player_tr| player_tr.translation.truncate());
 
-                    match bullet.angular_velocity.expression {
-                        IConst(_) => {}
-                        _ => {
-                            bullet_pool.add_modifier(BulletModifier {
-                                range: 0..bullets.len(),
-                                expression: bullet.angular_velocity.clone(),
-                                property: super::ModifierProperty::Angular,
-                            });
-                        }
-                    }
+        let live_instances: HashSet<Entity> =
+            pool_query.iter().flat_map(|p| p.live_instances()).collect();
+
+        for (entity, mut instance) in instance_query.iter_mut() {
+            instance.age += clock.step;
+
+            let age = instance.age;
+            let (mut due, pending): (Vec<_>, Vec<_>) =
+                instance.tasks.drain(..).partition(|task| task.time <= age);
+            due.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+            let mut executor = PatternExecutor::new(&mut commands, entity, player, age);
+            executor.pending = pending;
+
+            for task in due {
+                executor.run(&task.node, task.bullets, task.time);
+            }
+
+            let spawned = executor.spawned;
+            instance.tasks = executor.pending;
+
+            // Bullets spawned this step only reach their pool once commands are applied
+            if instance.tasks.is_empty() && !spawned && !live_instances.contains(&entity) {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
+
+/// Controls the bullets of patterns that have been fired, by their [`PatternInstanceId`].
+///
+/// Changes only reach bullets that existed at the start of the current frame,
+/// but they also apply to the parts of the pattern that haven't been fired yet.
+#[derive(SystemParam)]
+pub struct PatternInstances<'w, 's> {
+    commands: Commands<'w, 's>,
+    instance_query: Query<'w, 's, &'static mut PatternInstance>,
+    owner_query: Query<'w, 's, (Entity, &'static FiredBy)>,
+    pool_query: Query<'w, 's, &'static mut BulletPool>,
+}
+
+impl<'w, 's> PatternInstances<'w, 's> {
+    /// Whether the pattern still has bullets or scheduled operations.
+    pub fn is_active(&self, id: PatternInstanceId) -> bool {
+        self.instance_query.contains(id.0)
+    }
+
+    /// Number of bullets the pattern currently has.
+    pub fn alive(&self, id: PatternInstanceId) -> usize {
+        self.pool_query
+            .iter()
+            .map(|pool| pool.instance_len(id.0))
+            .sum()
+    }
+
+    /// Removes every bullet of the pattern and stops it from firing any more.
+    pub fn cancel(&mut self, id: PatternInstanceId) {
+        for mut pool in self.pool_query.iter_mut() {
+            pool.clear_instance(id.0);
+        }
+
+        if self.instance_query.contains(id.0) {
+            self.commands.entity(id.0).despawn();
+        }
+    }
+
+    /// Patterns fired by `owner` that are still active.
+    pub fn fired_by(&self, owner: Entity) -> Vec<PatternInstanceId> {
+        self.owner_query
+            .iter()
+            .filter(|(_, fired_by)| fired_by.0 == owner)
+            .map(|(entity, _)| PatternInstanceId(entity))
+            .collect()
+    }
+
+    /// Cancels every pattern fired by `owner`, e.g. when a boss changes phase.
+    pub fn cancel_fired_by(&mut self, owner: Entity) {
+        for id in self.fired_by(owner) {
+            self.cancel(id);
+        }
+    }
+
+    /// Moves every bullet of the pattern by `offset`.
+    pub fn translate(&mut self, id: PatternInstanceId, offset: Vec2) {
+        for mut pool in self.pool_query.iter_mut() {
+            pool.translate(id.0, offset);
+        }
+
+        self.for_each_pending(id, |bullet| bullet.position += offset);
+    }
+
+    /// Rotates every bullet of the pattern by `angle` radians around `pivot`.
+    pub fn rotate(&mut self, id: PatternInstanceId, angle: f32, pivot: Vec2) {
+        for mut pool in self.pool_query.iter_mut() {
+            pool.rotate(id.0, angle, pivot);
+        }
+
+        let rotation = Vec2::from_angle(angle);
+        self.for_each_pending(id, |bullet| {
+            bullet.position = pivot + rotation.rotate(bullet.position - pivot);
+            bullet.rotation += angle;
+        });
+    }
+
+    fn for_each_pending(&mut self, id: PatternInstanceId, mut f: impl FnMut(&mut BulletContext)) {
+        if let Ok(mut instance) = self.instance_query.get_mut(id.0) {
+            for task in instance.tasks.iter_mut() {
+                task.bullets.iter_mut().for_each(&mut f);
+            }
+        }
+    }
+}
+
+/// Walks a pattern tree, running every node that is due at `time`
+/// and collecting the ones that start later into `pending`.
+struct PatternExecutor<'a, 'w, 's> {
+    commands: &'a mut Commands<'w, 's>,
+    instance: Entity,
+    player: Option<Vec2>,
+    time: f32,
+    pending: Vec<PatternTask>,
+    spawned: bool,
+}
+
+impl<'a, 'w, 's> PatternExecutor<'a, 'w, 's> {
+    fn new(
+        commands: &'a mut Commands<'w, 's>,
+        instance: Entity,
+        player: Option<Vec2>,
+        time: f32,
+    ) -> Self {
+        Self {
+            commands,
+            instance,
+            player,
+            time,
+            pending: vec![],
+            spawned: false,
+        }
+    }
 
-                    commands.spawn(bullet_pool);
+    fn run(&mut self, node: &Arc<PatternNode>, bullets: Vec<BulletContext>, start: f32) {
+        if start > self.time {
+            self.pending.push(PatternTask {
+                time: start,
+                node: node.clone(),
+                bullets,
+            });
+            return;
+        }
+
+        let bullets = match &node.operation {
+            PatternOp::Ring(count, radius) => Pattern::ring(bullets, count, radius),
+            PatternOp::Arc(count, angle) => Pattern::arc(bullets, count, angle),
+            PatternOp::Aim(offset) => Pattern::aim(bullets, offset, self.player),
+            PatternOp::RandomSpread {
+                rotation,
+                speed,
+                distance,
+                salt,
+            } => Pattern::random_spread(bullets, rotation, speed, distance, *salt),
+            PatternOp::Bullet(bullet) => {
+                Pattern::spawn_bullets(bullet, &bullets, self.commands, self.instance);
+                self.spawned = true;
+                bullets
+            }
+            PatternOp::Repeat(count, interval) => {
+                let group = Self::group_context(&bullets);
+                let count = group.eval(count) as u32;
+                let interval = group.eval_duration(interval);
 
-                    bullets
+                for i in 0..count {
+                    let bullets: Vec<_> = bullets
+                        .iter()
+                        .map(|b| BulletContext {
+                            variables: BulletVariables {
+                                repeat_i: i as f64,
+                                repeat_n: count as f64,
+                                ..b.variables.child(i)
+                            },
+                            ..b.clone()
+                        })
+                        .collect();
+                    self.run_children(node, &bullets, start + i as f32 * interval);
                 }
+                return;
             }
+            PatternOp::Wait(duration) => {
+                let duration = Self::group_context(&bullets).eval_duration(duration);
+                self.run_children(node, &bullets, start + duration);
+                return;
+            }
+            PatternOp::Sequence => {
+                let group = Self::group_context(&bullets);
+                let mut start = start;
+                for child in node.children.iter() {
+                    self.run(child, bullets.clone(), start);
+                    start += child.duration(&group);
+                }
+                return;
+            }
+            PatternOp::Parallel => bullets,
+        };
+
+        self.run_children(node, &bullets, start);
+    }
+
+    /// Timing operations apply to a whole group of bullets at once,
+    /// so their expressions see the variables of the group's first bullet.
+    fn group_context(bullets: &[BulletContext]) -> BulletContext {
+        bullets.first().cloned().unwrap_or_default()
+    }
+
+    fn run_children(&mut self, node: &PatternNode, bullets: &[BulletContext], start: f32) {
+        for child in node.children.iter() {
+            self.run(child, bullets.to_vec(), start);
         }
     }
 }
 
 #[derive(Debug)]
 pub enum PatternOp {
-    Ring(Box<ExpressionSlab>, f32),
-    Arc(u32, f32 /* fasteval::Expression */),
+    /// Bullet count and distance from the center.
+    Ring(Box<ExpressionSlab>, Box<ExpressionSlab>),
+    /// Bullet count and spread angle, in degrees.
+    Arc(Box<ExpressionSlab>, Box<ExpressionSlab>),
+    /// Points the bullets at the player, plus an offset angle in degrees.
+    Aim(Box<ExpressionSlab>),
+    /// Randomly turns, speeds up and moves the bullets, up to an angle in degrees, a speed and a distance.
+    /// `salt` tells it apart from other operations on the same bullets, so they don't move in lockstep.
+    RandomSpread {
+        rotation: Box<ExpressionSlab>,
+        speed: Box<ExpressionSlab>,
+        distance: Box<ExpressionSlab>,
+        salt: u64,
+    },
     Bullet(BulletContext),
+    /// Runs the children `count` times, `interval` seconds apart.
+    Repeat(Box<ExpressionSlab>, Box<ExpressionSlab>),
+    /// Runs the children after a delay, in seconds.
+    Wait(Box<ExpressionSlab>),
+    /// Runs the children one after another, each starting when the previous one ends.
+    Sequence,
+    /// Runs the children at the same time.
+    Parallel,
+}
+
+/// Error raised when a pattern file can't be parsed.
+#[derive(Debug)]
+pub struct PatternError {
+    /// JSON path of the offending value, e.g. `child.child.speed`.
+    pub path: String,
+    /// Name of the offending field, e.g. `speed`.
+    pub field: String,
+    pub message: String,
+}
+
+impl PatternError {
+    fn new(path: &str, field: &str, message: impl Into<String>) -> Self {
+        Self {
+            path: join_path(path, field),
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
 }
 
-pub fn parse(source: &str) -> Pattern {
-    let json: serde_json::error::Result<Value> = serde_json::from_str(source);
+impl std::error::Error for PatternError {}
 
-    let mut value = &match json {
-        Err(error) => panic!("Error while parsing pattern: {error}"),
-        Ok(t) => t,
+fn join_path(path: &str, field: &str) -> String {
+    match (path.is_empty(), field.is_empty()) {
+        (true, _) => field.into(),
+        (_, true) => path.into(),
+        _ => format!("{path}.{field}"),
+    }
+}
+
+pub fn parse(source: &str) -> Result<Pattern, PatternError> {
+    let json: Value = serde_json::from_str(source)
+        .map_err(|error| PatternError::new("", "", format!("invalid JSON: {error}")))?;
+
+    let mut pattern = Pattern {
+        speed: 60.,
+        ..Default::default()
     };
 
-    let mut pattern = Pattern::default();
-
-    while !value.is_null() {
-        if let Value::String(element_type) = &value["type"] {
-            pattern.operations.push(match element_type.as_str() {
-                "ring" => PatternOp::Ring(
-                    Box::new(parse_expression(&value, "count")),
-                    value["radius"].as_f64().unwrap_or(0.) as f32,
-                ),
-                "arc" => PatternOp::Arc(
-                    value["count"].as_u64().expect("No count provided.") as u32,
-                    (value["angle"].as_f64().expect("No angle provided.") as f32).to_radians(),
-                ),
-                "bullet" => PatternOp::Bullet(BulletContext {
-                    lifetime: value["lifetime"].as_f64().unwrap_or(10.) as f32,
-                    speed: Arc::new(parse_expression(&value, "speed")),
-                    angular_velocity: Arc::new(parse_expression(&value, "angular_velocity")),
-                    id: value["id"].as_str().expect("No count provided.").into(),
-                    ..Default::default()
-                }),
-                _ => {
-                    panic!("Invalid type in pattern.");
-                }
-            });
+    // Settings for the whole pattern live on the root node
+    match &json["initial_speed"] {
+        Value::Null => {}
+        speed => {
+            pattern.speed = speed
+                .as_f64()
+                .ok_or_else(|| PatternError::new("", "initial_speed", "expected a number"))?
+                as f32
         }
+    }
 
-        value = &value["child"];
+    match &json["params"] {
+        Value::Null => {}
+        Value::Object(params) => {
+            for (name, value) in params {
+                let value = value
+                    .as_f64()
+                    .ok_or_else(|| PatternError::new("params", name, "expected a number"))?;
+                pattern.params.insert(name.clone(), value);
+            }
+        }
+        _ => return Err(PatternError::new("", "params", "expected an object")),
     }
 
-    pattern
+    // The root may be a single node or a list of nodes fired together
+    let nodes = match &json {
+        Value::Array(values) => values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| parse_node(&pattern, value, &format!("[{i}]")).map(Arc::new))
+            .collect::<Result<_, _>>()?,
+        value => vec![Arc::new(parse_node(&pattern, value, "")?)],
+    };
+
+    pattern.nodes = nodes;
+    Ok(pattern)
+}
+
+fn parse_node(pattern: &Pattern, value: &Value, path: &str) -> Result<PatternNode, PatternError> {
+    let operation = parse_operation(pattern, value, path)?;
+
+    let children = match (&value["child"], &value["children"]) {
+        (Value::Null, Value::Null) => vec![],
+        (child, Value::Null) => vec![Arc::new(parse_node(
+            pattern,
+            child,
+            &join_path(path, "child"),
+        )?)],
+        (Value::Null, Value::Array(children)) => children
+            .iter()
+            .enumerate()
+            .map(|(i, child)| {
+                parse_node(pattern, child, &join_path(path, &format!("children[{i}]")))
+                    .map(Arc::new)
+            })
+            .collect::<Result<_, _>>()?,
+        (Value::Null, _) => {
+            return Err(PatternError::new(path, "children", "expected an array"));
+        }
+        _ => {
+            return Err(PatternError::new(
+                path,
+                "children",
+                "a node can't have both `child` and `children`",
+            ));
+        }
+    };
+
+    Ok(PatternNode {
+        operation,
+        children,
+    })
+}
+
+fn parse_operation(
+    pattern: &Pattern,
+    value: &Value,
+    path: &str,
+) -> Result<PatternOp, PatternError> {
+    let Some(element_type) = value["type"].as_str() else {
+        return Err(PatternError::new(path, "type", "missing or not a string"));
+    };
+
+    Ok(match element_type {
+        "ring" => PatternOp::Ring(
+            Box::new(parse_expression(pattern, value, path, "count")?),
+            Box::new(parse_expression_or(pattern, value, path, "radius", 0.)?),
+        ),
+        "arc" => PatternOp::Arc(
+            Box::new(parse_expression(pattern, value, path, "count")?),
+            Box::new(parse_expression(pattern, value, path, "angle")?),
+        ),
+        "aim" => PatternOp::Aim(Box::new(parse_expression_or(
+            pattern, value, path, "offset", 0.,
+        )?)),
+        "random_spread" => PatternOp::RandomSpread {
+            rotation: Box::new(parse_expression_or(pattern, value, path, "rotation", 0.)?),
+            speed: Box::new(parse_expression_or(pattern, value, path, "speed", 0.)?),
+            distance: Box::new(parse_expression_or(pattern, value, path, "distance", 0.)?),
+            salt: hash_str(path),
+        },
+        "bullet" => PatternOp::Bullet(BulletContext {
+            lifetime: Arc::new(parse_expression_or(pattern, value, path, "lifetime", 10.)?),
+            speed: Arc::new(parse_expression_or(
+                pattern,
+                value,
+                path,
+                "speed",
+                pattern.speed as f64,
+            )?),
+            angular_velocity: Arc::new(parse_expression_or(
+                pattern,
+                value,
+                path,
+                "angular_velocity",
+                0.,
+            )?),
+            id: value["id"]
+                .as_str()
+                .ok_or_else(|| PatternError::new(path, "id", "missing or not a string"))?
+                .into(),
+            ..Default::default()
+        }),
+        "repeat" => PatternOp::Repeat(
+            Box::new(parse_expression(pattern, value, path, "count")?),
+            Box::new(parse_duration(pattern, value, path, "interval")?),
+        ),
+        "wait" => PatternOp::Wait(Box::new(parse_duration(pattern, value, path, "duration")?)),
+        "sequence" => PatternOp::Sequence,
+        "parallel" => PatternOp::Parallel,
+        other => {
+            return Err(PatternError::new(
+                path,
+                "type",
+                format!("unknown pattern type `{other}`"),
+            ))
+        }
+    })
+}
+
+fn parse_expression(
+    pattern: &Pattern,
+    value: &Value,
+    path: &str,
+    key: &str,
+) -> Result<ExpressionSlab, PatternError> {
+    match &value[key] {
+        Value::Null => Err(PatternError::new(path, key, "missing")),
+        Value::Number(number) => Ok(ExpressionSlab::constant(number.as_f64().unwrap_or(0.))),
+        Value::String(source) => {
+            let mut expression = ExpressionSlab::parse(source)
+                .map_err(|error| PatternError::new(path, key, format!("{error}")))?;
+            // Each expression draws its own random numbers
+            expression.salt = hash_str(&join_path(path, key));
+
+            // Catch undefined variables and functions now rather than when firing
+            let mut error = None;
+            expression.visit_names(&mut |name, args| {
+                if error.is_none() {
+                    error = check_name(pattern, name, args).err();
+                }
+            });
+            match error {
+                Some(message) => Err(PatternError::new(path, key, message)),
+                None => Ok(expression),
+            }
+        }
+        _ => Err(PatternError::new(
+            path,
+            key,
+            "expected a number or an expression string",
+        )),
+    }
 }
 
-fn parse_expression(value: &Value, key: &str) -> ExpressionSlab {
-    let binding = value[key].to_string();
-    let expression = binding.trim_matches('\"');
+/// Checks that an expression can use `name`: as a variable when `args` is `None`,
+/// or as a function taking that many arguments.
+fn check_name(pattern: &Pattern, name: &str, args: Option<usize>) -> Result<(), String> {
+    let function = BulletVariables::FUNCTIONS.iter().find(|(f, _)| *f == name);
+    let is_variable =
+        BulletVariables::default().lookup(name, 0.).is_some() || pattern.params.contains_key(name);
 
-    let string = expression;
-    let mut slab = Slab::new();
-    let expression = fasteval::Parser::new()
-        .parse(string, &mut slab.ps)
-        .unwrap()
-        .from(&slab.ps);
+    match (function, args) {
+        (Some((_, arities)), Some(args)) if !arities.contains(&args) => {
+            let expected: Vec<_> = arities.iter().map(|n| n.to_string()).collect();
+            Err(format!(
+                "wrong number of arguments for `{name}`: expected {}, got {args}",
+                expected.join(" or ")
+            ))
+        }
+        (Some(_), Some(_)) => Ok(()),
+        // Variables can be written like functions without arguments, `t()`
+        (_, None | Some(0)) if is_variable => Ok(()),
+        (_, None) => Err(format!("unknown variable `{name}`")),
+        (_, Some(_)) => Err(format!("unknown function `{name}`")),
+    }
+}
 
-    let expression = expression.compile(&slab.ps, &mut slab.cs);
+/// Times can be expressions too, but constant ones are checked right away.
+fn parse_duration(
+    pattern: &Pattern,
+    value: &Value,
+    path: &str,
+    key: &str,
+) -> Result<ExpressionSlab, PatternError> {
+    let expression = parse_expression(pattern, value, path, key)?;
+    match expression.expression {
+        IConst(time) if time.is_nan() || time < 0. => Err(PatternError::new(
+            path,
+            key,
+            "expected a time of 0 seconds or more",
+        )),
+        _ => Ok(expression),
+    }
+}
 
-    ExpressionSlab::new(expression, slab)
+fn parse_expression_or(
+    pattern: &Pattern,
+    value: &Value,
+    path: &str,
+    key: &str,
+    default: f64,
+) -> Result<ExpressionSlab, PatternError> {
+    match &value[key] {
+        Value::Null => Ok(ExpressionSlab::constant(default)),
+        _ => parse_expression(pattern, value, path, key),
+    }
 }
 
 #[derive(Debug)]
 pub struct ExpressionSlab {
     expression: Instruction,
     slab: Slab,
+    /// Mixed into the bullet's seed for the random functions
+    salt: u64,
+    /// Whether an evaluation error has been logged already, so it isn't logged for every bullet
+    reported: AtomicBool,
 }
 
 impl From<&str> for ExpressionSlab {
     fn from(value: &str) -> Self {
+        ExpressionSlab::parse(value).unwrap()
+    }
+}
+
+impl ExpressionSlab {
+    pub fn new(expression: Instruction, slab: Slab) -> Self {
+        Self {
+            expression,
+            slab,
+            salt: 0,
+            reported: AtomicBool::new(false),
+        }
+    }
+
+    pub fn constant(value: f64) -> Self {
+        Self::new(IConst(value), Slab::default())
+    }
+
+    pub fn parse(source: &str) -> Result<Self, fasteval::Error> {
         let mut slab = Slab::new();
         let expression = fasteval::Parser::new()
-            .parse(value, &mut slab.ps)
-            .unwrap()
+            .parse(source, &mut slab.ps)?
             .from(&slab.ps);
 
         let expression = expression.compile(&slab.ps, &mut slab.cs);
 
-        ExpressionSlab::new(expression, slab)
+        Ok(ExpressionSlab::new(expression, slab))
     }
-}
 
-impl ExpressionSlab {
-    pub fn new(expression: Instruction, slab: Slab) -> Self {
-        Self { expression, slab }
+    /// Gives 0 if the expression can't be evaluated, like when it uses an unknown name.
+    pub fn eval(&self, data: &mut impl EvalNamespace) -> f32 {
+        self.try_eval(data).unwrap_or_else(|error| {
+            if !self.reported.swap(true, Ordering::Relaxed) {
+                error!("Couldn't evaluate an expression, using 0 instead: {error}");
+            }
+            0.
+        })
     }
 
-    pub fn eval(&self, data: &mut impl EvalNamespace) -> f32 {
-        self.try_eval(data).unwrap()
+    /// Evaluates the expression for a single bullet that is `t` seconds old.
+    /// Its random functions give that bullet the same values every time.
+    pub fn eval_with(&self, variables: &BulletVariables, params: &PatternParams, t: f32) -> f32 {
+        let mut random = RandomStream::new(combine(variables.seed, self.salt));
+        self.eval(&mut |name: &str, args: Vec<f64>| {
+            variables
+                .call(name, &args, t as f64, &mut random)
+                .or_else(|| params.get(name).copied())
+        })
+    }
+
+    /// Calls `visit` with every variable the expression uses, and every function with its number
+    /// of arguments. Unlike evaluating it, this also sees what's behind `&&` and `||`.
+    fn visit_names(&self, visit: &mut impl FnMut(&str, Option<usize>)) {
+        visit_instruction(&self.slab, &self.expression, visit);
     }
 
     fn try_eval(&self, data: &mut impl EvalNamespace) -> Result<f32, fasteval::Error> {
@@ -341,3 +1105,208 @@ impl ExpressionSlab {
         Ok(fasteval::eval_compiled_ref!(&self.expression, &self.slab, &mut *data) as f32)
     }
 }
+
+fn visit_instruction(
+    slab: &Slab,
+    instruction: &Instruction,
+    visit: &mut impl FnMut(&str, Option<usize>),
+) {
+    use fasteval::compiler::{Instruction::*, IC};
+
+    let visit_ic = |ic: &IC, visit: &mut _| {
+        if let IC::I(i) = ic {
+            visit_instruction(slab, slab.cs.get_instr(*i), visit);
+        }
+    };
+
+    match instruction {
+        IConst(_) => {}
+        IVar(name) => visit(name, None),
+        // fasteval's own `var_names` doesn't look at the arguments
+        IFunc { name, args } => {
+            visit(name, Some(args.len()));
+            for arg in args {
+                visit_ic(arg, visit);
+            }
+        }
+        INeg(i) | INot(i) | IInv(i) | IFuncInt(i) | IFuncCeil(i) | IFuncFloor(i) | IFuncAbs(i)
+        | IFuncSign(i) | IFuncSin(i) | IFuncCos(i) | IFuncTan(i) | IFuncASin(i) | IFuncACos(i)
+        | IFuncATan(i) | IFuncSinH(i) | IFuncCosH(i) | IFuncTanH(i) | IFuncASinH(i)
+        | IFuncACosH(i) | IFuncATanH(i) => visit_instruction(slab, slab.cs.get_instr(*i), visit),
+        IAdd(i, ic)
+        | IMul(i, ic)
+        | IOR(i, ic)
+        | IAND(i, ic)
+        | IFuncMin(i, ic)
+        | IFuncMax(i, ic) => {
+            visit_instruction(slab, slab.cs.get_instr(*i), visit);
+            visit_ic(ic, visit);
+        }
+        ILT(a, b)
+        | ILTE(a, b)
+        | IEQ(a, b)
+        | INE(a, b)
+        | IGTE(a, b)
+        | IGT(a, b)
+        | IMod {
+            dividend: a,
+            divisor: b,
+        }
+        | IExp { base: a, power: b }
+        | IFuncLog { base: a, of: b }
+        | IFuncRound { modulus: a, of: b } => {
+            visit_ic(a, visit);
+            visit_ic(b, visit);
+        }
+        // Only `print` is left, which patterns have no use for
+        other => {
+            for name in other.var_names(slab) {
+                visit(&name, None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_speed(speed: &str) -> Result<Pattern, PatternError> {
+        parse(&format!(
+            r#"{{"type": "bullet", "id": "bullet", "speed": "{speed}"}}"#
+        ))
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_value() {
+        for (json, path, field, message) in [
+            (
+                r#"{"type": "ring", "count": 4, "child": {"type": "arc", "count": 3, "angle": 30,
+                    "child": {"type": "bullet", "id": "bullet", "speed": "fast"}}}"#,
+                "child.child.speed",
+                "speed",
+                "unknown variable `fast`",
+            ),
+            (
+                r#"{"type": "parallel", "children": [
+                    {"type": "ring", "count": 4},
+                    {"type": "arc", "count": [], "angle": 30}
+                ]}"#,
+                "children[1].count",
+                "count",
+                "expected a number or an expression string",
+            ),
+            (
+                r#"[{"type": "sequence"}, {"type": "spiral"}]"#,
+                "[1].type",
+                "type",
+                "unknown pattern type `spiral`",
+            ),
+            (
+                r#"{"type": "ring", "count": 4, "child": {"type": "bullet"}}"#,
+                "child.id",
+                "id",
+                "missing or not a string",
+            ),
+            (
+                r#"{"type": "ring", "count": 4, "child": {"type": "parallel",
+                    "child": {"type": "sequence"}, "children": []}}"#,
+                "child.children",
+                "children",
+                "a node can't have both `child` and `children`",
+            ),
+        ] {
+            let error = parse(json).unwrap_err();
+            assert_eq!(error.path, path, "{json}");
+            assert_eq!(error.field, field, "{json}");
+            assert_eq!(error.message, message, "{json}");
+        }
+    }
+
+    #[test]
+    fn random_functions_parse() {
+        for speed in ["rand()", "rand(1, 2)", "randn() * 10", "noise(t * 2)"] {
+            if let Err(error) = parse_speed(speed) {
+                panic!("`{speed}` didn't parse: {error}");
+            }
+        }
+    }
+
+    #[test]
+    fn aim_updates_player_angle() {
+        let bullet = BulletContext {
+            position: Vec2::new(10., 0.),
+            ..Default::default()
+        };
+        let offset = ExpressionSlab::from("player_angle / 10");
+
+        let aimed = Pattern::aim(vec![bullet], &offset, Some(Vec2::new(10., 10.)));
+        assert_eq!(aimed[0].variables.player_angle, 90.);
+        assert!((aimed[0].rotation - 99_f32.to_radians()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn negative_times_are_rejected() {
+        for (json, path) in [
+            (r#"{"type": "wait", "duration": -1}"#, "duration"),
+            (r#"{"type": "wait", "duration": "(-1) ^ 0.5"}"#, "duration"),
+            (
+                r#"{"type": "repeat", "count": 3, "interval": "-0.5"}"#,
+                "interval",
+            ),
+        ] {
+            let error = parse(json).unwrap_err();
+            assert_eq!(error.path, path, "{json}");
+            assert_eq!(error.message, "expected a time of 0 seconds or more");
+        }
+
+        // Only constants can be checked up front
+        assert!(parse(r#"{"type": "wait", "duration": "rand(-1, 1)"}"#).is_ok());
+    }
+
+    #[test]
+    fn unknown_names_are_rejected_everywhere() {
+        for (speed, message) in [
+            ("i > 0 && sped", "unknown variable `sped`"),
+            ("n || sped", "unknown variable `sped`"),
+            ("rand(1, sped)", "unknown variable `sped`"),
+            ("i == 0 || spread(2)", "unknown function `spread`"),
+        ] {
+            let error = parse_speed(speed).unwrap_err();
+            assert_eq!(error.path, "speed", "{speed}");
+            assert_eq!(error.message, message, "{speed}");
+        }
+
+        assert!(parse_speed("t() + i").is_ok());
+        assert!(parse(
+            r#"{"params": {"base": 60}, "type": "bullet", "id": "bullet", "speed": "i > 0 && base"}"#
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn failed_evaluations_give_zero() {
+        let expression = ExpressionSlab::from("sped + 1");
+        let params = PatternParams::default();
+        assert_eq!(
+            expression.eval_with(&BulletVariables::default(), &params, 0.),
+            0.
+        );
+    }
+
+    #[test]
+    fn random_functions_check_their_arguments() {
+        let error = parse_speed("60 + rand(1)").unwrap_err();
+        assert_eq!(error.path, "speed");
+        assert_eq!(
+            error.message,
+            "wrong number of arguments for `rand`: expected 0 or 2, got 1"
+        );
+
+        let error = parse_speed("noise()").unwrap_err();
+        assert_eq!(
+            error.message,
+            "wrong number of arguments for `noise`: expected 1, got 0"
+        );
+    }
+}
@@ -34,7 +34,9 @@ use bevy::{
     utils::{hashbrown::HashMap, FloatOrd},
 };
 
-use super::BulletPool;
+use crate::simulation::SimulationClock;
+
+use super::{bullet_type::BlendMode, BulletPool};
 
 #[derive(Default)]
 pub struct BulletPipelinePlugin;
@@ -100,19 +102,19 @@ impl FromWorld for BulletPipeline {
                         ),
                     },
                     count: None,
-                }, /* ,
-                   BindGroupLayoutEntry {
-                       binding: 1,
-                       visibility: ShaderStages::VERTEX,
-                       ty: BindingType::Buffer {
-                           ty: BufferBindingType::Storage { read_only: true },
-                           has_dynamic_offset: false,
-                           min_binding_size: Some(
-                               NonZeroU64::new(std::mem::size_of::<f32>() as u64).unwrap(),
-                           ),
-                       },
-                       count: None,
-                   }, */
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(
+                            NonZeroU64::new(std::mem::size_of::<Vec4>() as u64).unwrap(),
+                        ),
+                    },
+                    count: None,
+                },
             ],
             label: Some("bullet_layout"),
         });
@@ -151,12 +153,26 @@ impl FromWorld for BulletPipeline {
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
-pub struct BulletPipelineKey;
+pub struct BulletPipelineKey {
+    blend: BlendMode,
+}
 
 impl SpecializedRenderPipeline for BulletPipeline {
     type Key = BulletPipelineKey;
 
-    fn specialize(&self, _key: Self::Key) -> RenderPipelineDescriptor {
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let blend = match key.blend {
+            BlendMode::Alpha => BlendState::ALPHA_BLENDING,
+            BlendMode::Additive => BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            },
+        };
+
         RenderPipelineDescriptor {
             label: Some("bullet_pipeline".into()),
             layout: Some(vec![
@@ -177,7 +193,7 @@ impl SpecializedRenderPipeline for BulletPipeline {
                 targets: vec![Some(ColorTargetState {
                     // TODO: HDR support
                     format: TextureFormat::bevy_default(),
-                    blend: Some(BlendState::ALPHA_BLENDING),
+                    blend: Some(blend),
                     write_mask: ColorWrites::ALL,
                 })],
             }),
@@ -209,19 +225,32 @@ struct ExtractedBulletPools {
 }
 
 struct ExtractedBulletPool {
+    /// Positions and sprite rotations, which include the type's spin
     states: Vec<Vec3A>,
+    size: f32,
+    color: [f32; 4],
+    blend: BlendMode,
     handle: Handle<Image>,
 }
 
 fn extract_bullets(
     mut extracted_pools: ResMut<ExtractedBulletPools>,
     pools: Extract<Query<&BulletPool>>,
+    clock: Extract<Res<SimulationClock>>,
 ) {
     extracted_pools.pools.clear();
 
-    pools.iter().for_each(|p| {
+    pools.iter().filter(|p| !p.is_empty()).for_each(|p| {
+        let definition = p.definition();
         extracted_pools.pools.push(ExtractedBulletPool {
-            states: p.states.clone(),
+            states: p
+                .live_interpolated_states(clock.overstep())
+                .zip(p.live_ages())
+                .map(|(state, age)| state + Vec3A::Z * definition.spin * age)
+                .collect(),
+            size: definition.size,
+            color: definition.color.as_linear_rgba_f32(),
+            blend: definition.blend,
             handle: p.handle.clone(),
         })
     });
@@ -230,6 +259,7 @@ fn extract_bullets(
 #[derive(Component, Default)]
 struct BulletBatch {
     handle: Handle<Image>,
+    blend: BlendMode,
     range: Range<u32>,
 }
 
@@ -240,12 +270,14 @@ pub struct BulletMeta {
     material_bind_groups: HashMap<Handle<Image>, BindGroup>,
 
     positions: BufferVec<__m128>,
+    colors: BufferVec<[f32; 4]>,
 }
 
 impl Default for BulletMeta {
     fn default() -> Self {
         Self {
             positions: BufferVec::new(BufferUsages::STORAGE),
+            colors: BufferVec::new(BufferUsages::STORAGE),
             material_bind_groups: Default::default(),
             view_bind_group: Default::default(),
             bullet_states_bind_group: Default::default(),
@@ -260,11 +292,18 @@ fn prepare_bullets(
     mut bullet_meta: ResMut<BulletMeta>,
     mut extracted_pools: ResMut<ExtractedBulletPools>,
 ) {
-    let BulletMeta { positions, .. }: &mut BulletMeta = bullet_meta.as_mut();
-
-    fn spawn_bullet_batch(commands: &mut Commands, handle: &Handle<Image>, range: &Range<u32>) {
+    let BulletMeta {
+        positions, colors, ..
+    }: &mut BulletMeta = bullet_meta.as_mut();
+
+    fn spawn_bullet_batch(
+        commands: &mut Commands,
+        (blend, handle): &(BlendMode, Handle<Image>),
+        range: &Range<u32>,
+    ) {
         commands.spawn(BulletBatch {
             handle: handle.clone_weak(),
+            blend: *blend,
             range: range.clone(),
         });
     }
@@ -272,11 +311,11 @@ fn prepare_bullets(
     let span = info_span!("buffer_clear_and_setup").entered();
 
     positions.clear();
-    // rotations.clear();
+    colors.clear();
 
     extracted_pools
         .pools
-        .sort_by(|a, b| a.handle.cmp(&b.handle));
+        .sort_by(|a, b| (a.blend, &a.handle).cmp(&(b.blend, &b.handle)));
 
     let total = extracted_pools
         .pools
@@ -285,38 +324,40 @@ fn prepare_bullets(
         .sum::<usize>();
 
     positions.reserve(total, &render_device);
-    // rotations.reserve(total, &render_device);
+    colors.reserve(total, &render_device);
 
     span.exit();
 
     let mut range = 0..0u32;
-    let mut handle: Option<Handle<Image>> = None;
+    let mut batch: Option<(BlendMode, Handle<Image>)> = None;
 
     for pool in extracted_pools.pools.iter() {
-        if let Some(handle) = &handle {
-            if *handle != pool.handle {
-                spawn_bullet_batch(&mut commands, handle, &range);
+        if let Some(batch) = &batch {
+            if *batch != (pool.blend, pool.handle.clone_weak()) {
+                spawn_bullet_batch(&mut commands, batch, &range);
                 range.start = range.end;
             }
         }
 
         let _span = info_span!("buffer_move").entered();
 
+        // The size goes in the padding after each state
         pool.states.iter().for_each(|s| {
-            positions.push(From::from(*s));
+            positions.push(From::from(s.extend(pool.size)));
+            colors.push(pool.color);
         });
         range.end += pool.states.len() as u32 * 6;
-        handle = Some(pool.handle.clone_weak());
+        batch = Some((pool.blend, pool.handle.clone_weak()));
     }
 
     if !range.is_empty() {
-        spawn_bullet_batch(&mut commands, &handle.unwrap(), &range);
+        spawn_bullet_batch(&mut commands, &batch.unwrap(), &range);
     }
 
     let _span = info_span!("buffer_write").entered();
 
     positions.write_buffer(&render_device, &render_queue);
-    // rotations.write_buffer(&render_device, &render_queue);
+    colors.write_buffer(&render_device, &render_queue);
 }
 
 fn bind_buffer<T: Pod>(buffer: &BufferVec<T>, count: u64) -> BindingResource {
@@ -371,14 +412,11 @@ fn queue_bullets(
                         &bullet_meta.positions,
                         bullet_meta.positions.len() as u64,
                     ),
-                }, /* ,
-                   BindGroupEntry {
-                       binding: 1,
-                       resource: bind_buffer(
-                           &bullet_meta.rotations,
-                           bullet_meta.positions.len() as u64,
-                       ),
-                   }, */
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: bind_buffer(&bullet_meta.colors, bullet_meta.colors.len() as u64),
+                },
             ],
         }));
 
@@ -412,7 +450,11 @@ fn queue_bullets(
             transparent_phase.add(Transparent2d {
                 entity,
                 draw_function: draw_bullet,
-                pipeline: pipelines.specialize(&mut pipeline_cache, &pipeline, BulletPipelineKey),
+                pipeline: pipelines.specialize(
+                    &mut pipeline_cache,
+                    &pipeline,
+                    BulletPipelineKey { blend: batch.blend },
+                ),
                 sort_key: FloatOrd(0.),
                 // This material is not batched
                 batch_range: Some(batch.range.clone()),
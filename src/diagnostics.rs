@@ -1,11 +1,14 @@
 use bevy::{
-    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    diagnostic::{Diagnostic, DiagnosticId, Diagnostics, FrameTimeDiagnosticsPlugin},
     prelude::*,
     time::FixedTimestep,
 };
 use bevy_egui::{egui, EguiContext};
 
-use crate::bullet::BulletPool;
+use crate::{
+    bullet::BulletPool,
+    player::{GrazeCount, Lives, Player},
+};
 
 /// Originally from the ScreenDiags crate: https://github.com/jomala/bevy_screen_diags
 pub struct DebugInfoPlugin;
@@ -19,10 +22,32 @@ impl Plugin for DebugInfoPlugin {
                     .with_system(extract_fps)
                     .with_run_criteria(FixedTimestep::steps_per_second(2.)),
             )
+            .add_startup_system(setup_diagnostics)
+            .add_system(measure_bullets)
             .add_system_to_stage(CoreStage::PostUpdate, update);
     }
 }
 
+pub const BULLET_COUNT: DiagnosticId = DiagnosticId::from_u128(0x8d6c3b4e6a7f4a1bb1f2c9d04e3a5f01);
+pub const BULLET_SLOTS: DiagnosticId = DiagnosticId::from_u128(0x8d6c3b4e6a7f4a1bb1f2c9d04e3a5f02);
+pub const BULLET_POOLS: DiagnosticId = DiagnosticId::from_u128(0x8d6c3b4e6a7f4a1bb1f2c9d04e3a5f03);
+
+fn setup_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(BULLET_COUNT, "bullets", 20));
+    diagnostics.add(Diagnostic::new(BULLET_SLOTS, "bullet_slots", 20));
+    diagnostics.add(Diagnostic::new(BULLET_POOLS, "bullet_pools", 20));
+}
+
+fn measure_bullets(mut diagnostics: ResMut<Diagnostics>, bullet_pools: Query<&BulletPool>) {
+    diagnostics.add_measurement(BULLET_COUNT, || {
+        bullet_pools.iter().map(|p| p.len()).sum::<usize>() as f64
+    });
+    diagnostics.add_measurement(BULLET_SLOTS, || {
+        bullet_pools.iter().map(|p| p.capacity()).sum::<usize>() as f64
+    });
+    diagnostics.add_measurement(BULLET_POOLS, || bullet_pools.iter().count() as f64);
+}
+
 #[derive(Resource, Default)]
 pub struct Framerate {
     average: f64,
@@ -31,6 +56,8 @@ pub struct Framerate {
 
 fn update(
     bullet_pools: Query<&BulletPool>,
+    lives_query: Query<&Lives, With<Player>>,
+    graze_count: Res<GrazeCount>,
     framerate: Res<Framerate>,
     mut ctx: ResMut<EguiContext>,
 ) {
@@ -42,7 +69,35 @@ fn update(
                 "FPS: {:.0} (min {:.0})",
                 framerate.average, framerate.min
             ));
-            ui.label(format!("Bullets: {:.0}", bullet_pools.iter().map(|p| p.len()).sum::<usize>()));
+            if let Ok(lives) = lives_query.get_single() {
+                ui.label(format!(
+                    "Lives: {}{}",
+                    lives.count,
+                    if lives.is_invulnerable() {
+                        " (invulnerable)"
+                    } else {
+                        ""
+                    }
+                ));
+            }
+            ui.label(format!("Graze: {}", graze_count.0));
+            ui.label(format!(
+                "Bullets: {} ({} slots in {} pools)",
+                bullet_pools.iter().map(|p| p.len()).sum::<usize>(),
+                bullet_pools.iter().map(|p| p.capacity()).sum::<usize>(),
+                bullet_pools.iter().count()
+            ));
+
+            let mut pools: Vec<_> = bullet_pools.iter().collect();
+            pools.sort_by(|a, b| a.bullet_type().cmp(b.bullet_type()));
+            for pool in pools {
+                ui.label(format!(
+                    "  {}: {} / {}",
+                    pool.bullet_type(),
+                    pool.len(),
+                    pool.capacity()
+                ));
+            }
         });
 }
 
@@ -54,7 +109,7 @@ fn extract_fps(mut framerate: ResMut<Framerate>, diagnostics: Res<Diagnostics>)
                 fps.average(),
                 fps.measurements()
                     .map(|m| m.value)
-                    .min_by(|m1, m2| m1.total_cmp(&m2))
+                    .min_by(|m1, m2| m1.total_cmp(&m2)),
             ))
         })
     {
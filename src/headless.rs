@@ -0,0 +1,160 @@
+use std::{
+    path::Path,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    asset::{AssetPlugin, LoadState},
+    ecs::system::Command,
+    prelude::*,
+    time::TimePlugin,
+    transform::TransformPlugin,
+};
+
+use crate::{
+    bullet::{
+        pattern::{BulletVariables, Pattern},
+        BulletPlugin, BulletPool, FirePattern, NewBullet, SpawnBullets,
+    },
+    player::Player,
+    replay::StepInput,
+    simulation::{SimulationAssets, SimulationClock, SimulationPlugin, SimulationSeed},
+};
+
+/// The bullet simulation without a window, GPU or real clock, moved forward one step at a time.
+/// Runs patterns for the headless runner and the pattern snapshot tests.
+pub struct HeadlessSimulation {
+    pub app: App,
+}
+
+impl HeadlessSimulation {
+    /// Runs start from the same seed, unless it's changed with [`HeadlessSimulation::seed`].
+    pub fn new(steps_per_second: f32) -> Self {
+        let mut app = App::new();
+        // Time stays at zero, the simulation only moves when it's told to
+        app.add_plugins(MinimalPlugins.build().disable::<TimePlugin>())
+            .init_resource::<Time>()
+            .add_plugin(TransformPlugin)
+            .add_plugin(AssetPlugin::default())
+            .add_plugin(SimulationPlugin)
+            .add_plugin(BulletPlugin)
+            .insert_resource(SimulationClock::from_rate(steps_per_second))
+            .insert_resource(SimulationSeed(0))
+            .init_resource::<StepInput>();
+
+        Self { app }
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.app.insert_resource(SimulationSeed(seed));
+    }
+
+    pub fn spawn_player(&mut self, position: Vec2) -> Entity {
+        self.app
+            .world
+            .spawn((Transform::from_translation(position.extend(0.)), Player))
+            .id()
+    }
+
+    /// Loads a pattern file, from the assets folder or from the project root,
+    /// then waits for it and every asset the simulation needs to be ready.
+    pub fn load_pattern(&mut self, path: impl AsRef<Path>) -> Result<Handle<Pattern>, String> {
+        let path = path.as_ref();
+        let path = path.strip_prefix("assets").unwrap_or(path);
+        let pattern: Handle<Pattern> = self.app.world.resource::<AssetServer>().load(path);
+
+        self.wait_until(&path.display().to_string(), |world| {
+            match world.resource::<AssetServer>().get_load_state(&pattern) {
+                LoadState::Failed => Err(format!("couldn't load {}", path.display())),
+                state => Ok(state == LoadState::Loaded),
+            }
+        })?;
+        Ok(pattern)
+    }
+
+    /// Waits for every asset the simulation needs, like the bullet type definitions, to be ready.
+    pub fn wait_for_assets(&mut self) -> Result<(), String> {
+        self.wait_until("the simulation's assets", |_| Ok(true))
+    }
+
+    fn wait_until(
+        &mut self,
+        what: &str,
+        mut ready: impl FnMut(&World) -> Result<bool, String>,
+    ) -> Result<(), String> {
+        let started = Instant::now();
+        loop {
+            self.app.update();
+
+            let world = &self.app.world;
+            if ready(world)? && world.resource::<SimulationAssets>().is_loaded() {
+                // One more update for the loaded assets' events to go through
+                self.app.update();
+                return Ok(());
+            }
+            if started.elapsed() > Duration::from_secs(10) {
+                return Err(format!("timed out while loading {what}"));
+            }
+
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Adds bullets that stay where they are, straight to their type's pool, without a pattern.
+    pub fn spawn_still_bullets(
+        &mut self,
+        bullet_type: &str,
+        positions: impl IntoIterator<Item = Vec2>,
+    ) {
+        let instance = self.app.world.spawn_empty().id();
+        SpawnBullets {
+            bullet_type: bullet_type.into(),
+            bullets: positions
+                .into_iter()
+                .map(|position| NewBullet {
+                    position,
+                    rotation: 0.,
+                    speed: 0.,
+                    angular: 0.,
+                    lifetime: f32::INFINITY,
+                    variables: BulletVariables::default(),
+                })
+                .collect(),
+            modifiers: Arc::new([]),
+            params: Arc::default(),
+            instance,
+        }
+        .write(&mut self.app.world);
+    }
+
+    /// Fires a pattern on the next step.
+    pub fn fire(&mut self, event: FirePattern) {
+        self.app
+            .world
+            .resource_mut::<Events<FirePattern>>()
+            .send(event);
+    }
+
+    pub fn step(&mut self) {
+        self.app.world.resource_mut::<SimulationClock>().advance(1);
+        self.app.update();
+    }
+
+    pub fn clock(&self) -> &SimulationClock {
+        self.app.world.resource::<SimulationClock>()
+    }
+
+    /// Every bullet pool, sorted by bullet type.
+    pub fn pools(&mut self) -> Vec<&BulletPool> {
+        let mut pools: Vec<_> = self
+            .app
+            .world
+            .query::<&BulletPool>()
+            .iter(&self.app.world)
+            .collect();
+        pools.sort_by(|a, b| a.bullet_type().cmp(b.bullet_type()));
+        pools
+    }
+}
@@ -0,0 +1,8 @@
+pub mod bullet;
+pub mod diagnostics;
+pub mod editor;
+pub mod headless;
+pub mod player;
+pub mod random;
+pub mod replay;
+pub mod simulation;
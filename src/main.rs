@@ -1,17 +1,21 @@
-mod bullet;
-mod diagnostics;
-mod editor;
-mod player;
-
 use bevy::{diagnostic::LogDiagnosticsPlugin, prelude::*};
 
 use bevy_egui::EguiPlugin;
-use bullet::BulletPlugin;
-use diagnostics::DebugInfoPlugin;
-use editor::EditorPlugin;
-use player::PlayerPlugin;
+use simidi::{
+    bullet::{BulletPipelinePlugin, BulletPlugin},
+    diagnostics::DebugInfoPlugin,
+    editor::EditorPlugin,
+    player::{self, PlayerPlugin},
+    replay::ReplayPlugin,
+    simulation::{Interpolated, SimulationPlugin},
+};
 
 fn main() {
+    let replay = ReplayPlugin::from_args(std::env::args().skip(1)).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(1)
+    });
+
     App::new()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             window: WindowDescriptor {
@@ -24,24 +28,33 @@ fn main() {
         }))
         .insert_resource(ClearColor(Color::MIDNIGHT_BLUE))
         .add_plugin(EguiPlugin)
+        .add_plugin(SimulationPlugin)
+        .add_plugin(replay)
         .add_plugin(LogDiagnosticsPlugin::default())
         .add_plugin(DebugInfoPlugin)
         .add_plugin(EditorPlugin)
         .add_plugin(PlayerPlugin)
         .add_plugin(BulletPlugin)
+        .add_plugin(BulletPipelinePlugin)
         .add_startup_system(setup)
         .run();
 }
 
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn((
-        SpriteBundle {
-            texture: asset_server.load("player_temp.png"),
-            transform: Transform::from_xyz(0., 100., 0.),
-            ..Default::default()
-        },
-        player::Player,
-    ));
+    // The sprite is a child so it can be drawn between simulation steps
+    commands
+        .spawn((
+            SpatialBundle::from_transform(Transform::from_xyz(0., 100., 0.)),
+            player::Player,
+            player::Lives::new(3, 2.),
+            Interpolated::default(),
+        ))
+        .with_children(|parent| {
+            parent.spawn(SpriteBundle {
+                texture: asset_server.load("player_temp.png"),
+                ..Default::default()
+            });
+        });
 
     commands.spawn(Camera2dBundle::default());
 }
@@ -1,33 +1,82 @@
-use crate::editor::is_ui_unfocused;
+use crate::{
+    bullet::{Graze, PlayerHit},
+    replay::StepInput,
+    simulation::{SimulationApp, SimulationClock, SimulationStage},
+};
 use bevy::prelude::*;
 
+/// Player movement speed, in units per second
+const PLAYER_SPEED: f32 = 60.;
+
+#[derive(Component)]
+pub struct Player;
+
+/// Lives left, lost one at a time when the player is hit.
+/// After a hit, the player passes through bullets for `invulnerability` seconds.
 #[derive(Component)]
-pub(crate) struct Player;
+pub struct Lives {
+    pub count: u32,
+    pub invulnerability: f32,
+    invulnerable_for: f32,
+}
+
+impl Lives {
+    pub fn new(count: u32, invulnerability: f32) -> Self {
+        Self {
+            count,
+            invulnerability,
+            invulnerable_for: 0.,
+        }
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable_for > 0.
+    }
+}
+
+/// Number of bullets the player has grazed so far.
+#[derive(Resource, Default)]
+pub struct GrazeCount(pub u32);
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(player_controls.with_run_criteria(is_ui_unfocused));
+        app.init_resource::<GrazeCount>()
+            .add_system_to_simulation(SimulationStage::Input, player_controls)
+            .add_system_to_simulation(SimulationStage::PostTick, take_hits)
+            .add_system_to_simulation(SimulationStage::PostTick, count_grazes);
     }
 }
 
+fn take_hits(
+    mut query: Query<&mut Lives, With<Player>>,
+    mut hit_events: EventReader<PlayerHit>,
+    clock: Res<SimulationClock>,
+) {
+    let hit = hit_events.iter().count() > 0;
+    let Ok(mut lives) = query.get_single_mut() else { return };
+
+    lives.invulnerable_for = (lives.invulnerable_for - clock.step).max(0.);
+
+    if hit && !lives.is_invulnerable() && lives.count > 0 {
+        lives.count -= 1;
+        lives.invulnerable_for = lives.invulnerability;
+    }
+}
+
+fn count_grazes(mut graze_count: ResMut<GrazeCount>, mut graze_events: EventReader<Graze>) {
+    graze_count.0 += graze_events.iter().count() as u32;
+}
+
 // Simple 8-directional player controls
 fn player_controls(
     mut query: Query<&mut Transform, With<Player>>,
-    key_input: Res<Input<KeyCode>>,
+    input: Res<StepInput>,
+    clock: Res<SimulationClock>,
 ) {
     let mut player_tr = query.single_mut();
-    let h_movement = key_input.any_pressed([KeyCode::D, KeyCode::Right]) as i32
-        - key_input.any_pressed([KeyCode::A, KeyCode::Left]) as i32;
-    let v_movement = key_input.any_pressed([KeyCode::W, KeyCode::Up]) as i32
-        - key_input.any_pressed([KeyCode::S, KeyCode::Down]) as i32;
-
-    let movement = Vec2 {
-        x: h_movement as f32,
-        y: v_movement as f32,
-    }
-    .normalize_or_zero();
+    let movement = input.movement.as_vec2().normalize_or_zero();
 
-    player_tr.translation += movement.extend(0.);
+    player_tr.translation += movement.extend(0.) * PLAYER_SPEED * clock.step;
 }
@@ -0,0 +1,164 @@
+use std::f64::consts::TAU;
+
+use bevy::prelude::*;
+
+use crate::simulation::SimulationSeed;
+
+/// Added to the state on every draw, as in SplitMix64
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Random numbers for the simulation, reseeded from [`SimulationSeed`] whenever it changes.
+/// Systems drawing from it must run in a fixed order, or runs stop being reproducible.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct SimulationRng {
+    state: u64,
+}
+
+impl SimulationRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        mix(self.state)
+    }
+
+    pub(crate) fn reseed(seed: Res<SimulationSeed>, mut rng: ResMut<SimulationRng>) {
+        if seed.is_changed() {
+            *rng = SimulationRng::new(seed.0);
+        }
+    }
+}
+
+/// Random numbers that only depend on a seed, for code that can't draw from [`SimulationRng`]
+/// in a fixed order, like pattern expressions evaluated in parallel.
+#[derive(Clone, Copy, Debug)]
+pub struct RandomStream {
+    seed: u64,
+    draws: u64,
+}
+
+impl RandomStream {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, draws: 0 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.draws += 1;
+        combine(self.seed, self.draws)
+    }
+
+    /// Uniformly distributed between 0 (included) and 1 (excluded).
+    pub fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniformly distributed between `min` and `max`.
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.uniform()
+    }
+
+    /// Normally distributed, with a mean of 0 and a standard deviation of 1.
+    pub fn normal(&mut self) -> f64 {
+        // Box-Muller, with the first number kept away from zero
+        let radius = (-2. * (1. - self.uniform()).ln()).sqrt();
+        radius * (TAU * self.uniform()).cos()
+    }
+
+    /// Smooth noise between -1 and 1, which goes through a random value at every whole `x`.
+    /// Doesn't count as a draw: the same `x` always gives the same value.
+    pub fn noise(&self, x: f64) -> f64 {
+        let value = |cell: f64| {
+            let bits = combine(self.seed ^ GOLDEN_GAMMA, cell as i64 as u64);
+            (bits >> 11) as f64 / (1u64 << 52) as f64 - 1.
+        };
+
+        let cell = x.floor();
+        let f = x - cell;
+        let smooth = f * f * (3. - 2. * f);
+        value(cell) + (value(cell + 1.) - value(cell)) * smooth
+    }
+}
+
+/// Scrambles the bits of `value`, with the SplitMix64 finalizer.
+pub fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// Derives a new seed from a seed and a value, e.g. an index.
+pub fn combine(seed: u64, value: u64) -> u64 {
+    mix(seed ^ mix(value.wrapping_add(GOLDEN_GAMMA)))
+}
+
+/// Hashes a string into a seed. Unlike the standard library's hashers,
+/// the result is guaranteed to stay the same between builds, so recorded runs keep playing back.
+pub fn hash_str(value: &str) -> u64 {
+    // FNV-1a
+    value.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draws(mut stream: RandomStream) -> Vec<u64> {
+        (0..100).map(|_| stream.next_u64()).collect()
+    }
+
+    #[test]
+    fn same_seed_same_numbers() {
+        assert_eq!(draws(RandomStream::new(42)), draws(RandomStream::new(42)));
+        assert_ne!(draws(RandomStream::new(42)), draws(RandomStream::new(43)));
+
+        let mut a = SimulationRng::new(42);
+        let mut b = SimulationRng::new(42);
+        let mut c = SimulationRng::new(43);
+        for _ in 0..100 {
+            let next = a.next_u64();
+            assert_eq!(next, b.next_u64());
+            assert_ne!(next, c.next_u64());
+        }
+    }
+
+    #[test]
+    fn range_stays_in_bounds() {
+        let mut stream = RandomStream::new(7);
+        for _ in 0..10_000 {
+            let value = stream.range(-3., 5.);
+            assert!((-3. ..5.).contains(&value), "{value} out of range");
+        }
+    }
+
+    #[test]
+    fn normal_is_centered() {
+        let mut stream = RandomStream::new(7);
+        let values: Vec<_> = (0..10_000).map(|_| stream.normal()).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+        assert!(mean.abs() < 0.05, "mean {mean}");
+        assert!((variance - 1.).abs() < 0.05, "variance {variance}");
+    }
+
+    #[test]
+    fn noise_is_bounded_and_continuous() {
+        let stream = RandomStream::new(7);
+        for i in -1000..1000 {
+            let x = i as f64 / 37.;
+            let value = stream.noise(x);
+            assert!((-1. ..1.).contains(&value), "noise({x}) = {value}");
+        }
+
+        for x in -20..20 {
+            let x = x as f64;
+            let at = stream.noise(x);
+            for near in [x - 1e-9, x + 1e-9] {
+                assert!((stream.noise(near) - at).abs() < 1e-6, "jump at {x}");
+            }
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    error::Error,
+    fmt, fs, io, panic,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError, Weak},
+};
+
+use bevy::{app::AppExit, prelude::*};
+use serde_json::{json, Value};
+
+use crate::{
+    editor::{is_ui_unfocused, EditorState},
+    simulation::{SimulationApp, SimulationClock, SimulationSeed, SimulationStage},
+};
+
+/// Turns the keyboard into [`StepInput`]s, and records them or plays them back.
+#[derive(Default)]
+pub struct ReplayPlugin {
+    pub mode: ReplayMode,
+}
+
+#[derive(Clone, Default)]
+pub enum ReplayMode {
+    /// Play from the keyboard
+    #[default]
+    Live,
+    /// Play from the keyboard, and save the session to a file on exit
+    Record(PathBuf),
+    /// Play a recorded session
+    Play(Replay),
+}
+
+impl ReplayPlugin {
+    /// Reads `--record <file>` or `--replay <file>` from the command line arguments.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, ReplayError> {
+        let mut mode = ReplayMode::Live;
+        while let Some(arg) = args.next() {
+            let mut path = || {
+                args.next()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| ReplayError::Invalid(format!("{arg} expects a file")))
+            };
+
+            match arg.as_str() {
+                "--record" => mode = ReplayMode::Record(path()?),
+                "--replay" => mode = ReplayMode::Play(Replay::load(path()?)?),
+                _ => {}
+            }
+        }
+
+        Ok(Self { mode })
+    }
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        let state = match &self.mode {
+            ReplayMode::Live => ReplayState::Live,
+            ReplayMode::Record(path) => {
+                let replay = Arc::new(Mutex::new(Replay::default()));
+                save_on_panic(Arc::downgrade(&replay), path.clone());
+                ReplayState::Recording {
+                    replay,
+                    path: path.clone(),
+                }
+            }
+            ReplayMode::Play(replay) => {
+                // Same step length and seed as the session, for it to play out the same way.
+                // SimulationPlugin keeps both, whether it's added before or after this plugin
+                app.init_resource::<SimulationClock>();
+                app.world.resource_mut::<SimulationClock>().step = replay.step;
+                app.insert_resource(SimulationSeed(replay.seed));
+                ReplayState::Playing {
+                    replay: replay.clone(),
+                    position: 0,
+                }
+            }
+        };
+
+        app.insert_resource(state)
+            .init_resource::<StepInput>()
+            .init_resource::<QueuedFire>()
+            .add_system(queue_fire.with_run_criteria(is_ui_unfocused))
+            .add_system_to_simulation(SimulationStage::PreTick, clear_input)
+            .add_system_to_simulation(
+                SimulationStage::PreTick,
+                read_keyboard
+                    .with_run_criteria(is_ui_unfocused)
+                    .after(clear_input),
+            )
+            .add_system_to_simulation(
+                SimulationStage::PreTick,
+                ReplayState::step.after(read_keyboard),
+            )
+            .add_system_to_stage(CoreStage::Last, ReplayState::save_on_exit);
+    }
+}
+
+/// What the player does during the current simulation step.
+/// Simulation systems react to this rather than to the keyboard, so sessions can be replayed.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StepInput {
+    /// -1, 0 or 1 on each axis
+    pub movement: IVec2,
+    /// Name of the pattern to fire, if any
+    pub fire: Option<String>,
+}
+
+/// A recorded session: the input for every simulation step, and what it needs to play out the same.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    /// Length of a simulation step, in seconds
+    pub step: f32,
+    pub inputs: Vec<StepInput>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            step: SimulationClock::default().step,
+            inputs: vec![],
+        }
+    }
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ReplayError> {
+        Ok(fs::write(path, self.to_json().to_string())?)
+    }
+
+    /// Each input is `[x, y]`, or `[x, y, "pattern"]` on steps where a pattern is fired.
+    pub fn to_json(&self) -> Value {
+        let inputs: Vec<Value> = self
+            .inputs
+            .iter()
+            .map(|input| match &input.fire {
+                Some(pattern) => json!([input.movement.x, input.movement.y, pattern]),
+                None => json!([input.movement.x, input.movement.y]),
+            })
+            .collect();
+
+        json!({
+            "seed": self.seed,
+            "step": self.step,
+            "inputs": inputs,
+        })
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ReplayError> {
+        let json: Value = serde_json::from_str(source)
+            .map_err(|error| ReplayError::Invalid(format!("invalid JSON: {error}")))?;
+
+        let seed = json["seed"]
+            .as_u64()
+            .ok_or_else(|| ReplayError::Invalid("`seed` should be a number".into()))?;
+        let step = json["step"]
+            .as_f64()
+            .filter(|step| *step > 0.)
+            .ok_or_else(|| ReplayError::Invalid("`step` should be a positive number".into()))?
+            as f32;
+        let inputs = json["inputs"]
+            .as_array()
+            .ok_or_else(|| ReplayError::Invalid("`inputs` should be an array".into()))?
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                parse_input(input)
+                    .ok_or_else(|| ReplayError::Invalid(format!("invalid input for step {i}")))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { seed, step, inputs })
+    }
+}
+
+fn parse_input(input: &Value) -> Option<StepInput> {
+    let input = input
+        .as_array()
+        .filter(|input| (2..=3).contains(&input.len()))?;
+    let axis = |value: &Value| value.as_i64().filter(|axis| (-1..=1).contains(axis));
+
+    Some(StepInput {
+        movement: IVec2::new(axis(&input[0])? as i32, axis(&input[1])? as i32),
+        fire: match input.get(2) {
+            Some(pattern) => Some(pattern.as_str()?.to_string()),
+            None => None,
+        },
+    })
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "couldn't access the replay: {error}"),
+            ReplayError::Invalid(message) => write!(f, "invalid replay: {message}"),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(error: io::Error) -> Self {
+        ReplayError::Io(error)
+    }
+}
+
+#[derive(Resource)]
+pub enum ReplayState {
+    Live,
+    /// Shared with the panic hook, so sessions that crash are saved too
+    Recording {
+        replay: Arc<Mutex<Replay>>,
+        path: PathBuf,
+    },
+    Playing {
+        replay: Replay,
+        position: usize,
+    },
+}
+
+impl ReplayState {
+    /// Records the step's input, or replaces it with the recorded one.
+    fn step(
+        mut state: ResMut<ReplayState>,
+        mut input: ResMut<StepInput>,
+        seed: Res<SimulationSeed>,
+        clock: Res<SimulationClock>,
+    ) {
+        match state.as_mut() {
+            ReplayState::Live => {}
+            ReplayState::Recording { replay, .. } => {
+                let mut replay = replay.lock().unwrap_or_else(PoisonError::into_inner);
+                if replay.inputs.is_empty() {
+                    replay.seed = seed.0;
+                    replay.step = clock.step;
+                }
+                replay.inputs.push(input.clone());
+            }
+            ReplayState::Playing { replay, position } => {
+                let Some(recorded) = replay.inputs.get(*position) else {
+                    info!("Replay finished after {position} steps");
+                    *state = ReplayState::Live;
+                    return;
+                };
+
+                *input = recorded.clone();
+                *position += 1;
+            }
+        }
+    }
+
+    fn save_on_exit(state: Res<ReplayState>, mut exit_events: EventReader<AppExit>) {
+        if exit_events.iter().count() == 0 {
+            return;
+        }
+
+        if let ReplayState::Recording { replay, path } = state.as_ref() {
+            save(&replay.lock().unwrap_or_else(PoisonError::into_inner), path);
+        }
+    }
+}
+
+fn save(replay: &Replay, path: &Path) {
+    match replay.save(path) {
+        Ok(()) => info!("Saved {} steps to {}", replay.inputs.len(), path.display()),
+        Err(error) => error!("Couldn't save the replay to {}: {error}", path.display()),
+    }
+}
+
+/// Saves the recording when the app panics, before the default hook reports the panic.
+/// Does nothing once the app, and its recording, are gone.
+fn save_on_panic(replay: Weak<Mutex<Replay>>, path: PathBuf) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // The panicking thread might be holding the lock, in the middle of recording a step
+        if let Some(replay) = replay.upgrade() {
+            if let Ok(replay) = replay.try_lock() {
+                save(&replay, &path);
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+/// A pattern fired since the last simulation step, by pressing E.
+#[derive(Resource, Default)]
+struct QueuedFire(Option<String>);
+
+// Key presses only last a frame, which might not have a simulation step
+fn queue_fire(
+    editor_state: Res<EditorState>,
+    key_input: Res<Input<KeyCode>>,
+    mut queued: ResMut<QueuedFire>,
+) {
+    if key_input.just_pressed(KeyCode::E) {
+        queued.0 = Some(editor_state.selected_pattern.clone());
+    }
+}
+
+fn clear_input(mut input: ResMut<StepInput>) {
+    *input = StepInput::default();
+}
+
+fn read_keyboard(
+    key_input: Res<Input<KeyCode>>,
+    mut queued: ResMut<QueuedFire>,
+    mut input: ResMut<StepInput>,
+) {
+    let h_movement = key_input.any_pressed([KeyCode::D, KeyCode::Right]) as i32
+        - key_input.any_pressed([KeyCode::A, KeyCode::Left]) as i32;
+    let v_movement = key_input.any_pressed([KeyCode::W, KeyCode::Up]) as i32
+        - key_input.any_pressed([KeyCode::S, KeyCode::Down]) as i32;
+
+    input.movement = IVec2::new(h_movement, v_movement);
+    input.fire = queued.0.take();
+}
@@ -0,0 +1,260 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::{
+    asset::{HandleId, LoadState},
+    ecs::{event::Event, schedule::ShouldRun},
+    prelude::*,
+    transform::TransformSystem,
+};
+
+use crate::random::SimulationRng;
+
+/// Runs the game in fixed steps, whatever the frame rate,
+/// so that the same inputs always play out exactly the same way.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationClock>()
+            .init_resource::<SimulationSeed>()
+            .init_resource::<SimulationRng>()
+            .init_resource::<SimulationAssets>()
+            .add_stage_after(
+                CoreStage::Update,
+                Simulation,
+                Schedule::default()
+                    .with_run_criteria(SimulationClock::should_step)
+                    .with_stage(
+                        SimulationStage::PreTick,
+                        SystemStage::parallel()
+                            .with_system(Interpolated::store_previous)
+                            .with_system(SimulationRng::reseed),
+                    )
+                    .with_stage(SimulationStage::Input, SystemStage::parallel())
+                    .with_stage(SimulationStage::Spawn, SystemStage::parallel())
+                    .with_stage(SimulationStage::Tick, SystemStage::parallel())
+                    .with_stage(SimulationStage::PostTick, SystemStage::parallel()),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                Interpolated::interpolate.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// The schedule holding the [`SimulationStage`]s, run once per step after [`CoreStage::Update`].
+#[derive(StageLabel)]
+pub struct Simulation;
+
+/// The stages of a simulation step, in order.
+/// Systems that touch the same data must be in different stages, or explicitly ordered.
+#[derive(StageLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SimulationStage {
+    /// Bookkeeping before anything moves
+    PreTick,
+    /// The player moves
+    Input,
+    /// Patterns and emitters fire
+    Spawn,
+    /// Bullets move and collide
+    Tick,
+    /// Reactions to collisions
+    PostTick,
+}
+
+/// Length of a simulation step, and how far the simulation is.
+#[derive(Resource)]
+pub struct SimulationClock {
+    /// Length of a step in seconds, 60 steps per second by default
+    pub step: f32,
+    tick: u64,
+    /// Frame time that hasn't been simulated yet
+    accumulator: f64,
+    /// Whether the schedule is going through the steps of the current frame
+    looping: bool,
+}
+
+impl Default for SimulationClock {
+    fn default() -> Self {
+        Self::from_rate(60.)
+    }
+}
+
+impl SimulationClock {
+    /// Steps a single frame can add at most, so a long hitch doesn't freeze the game while it catches up
+    const MAX_STEPS_PER_FRAME: f64 = 8.;
+
+    pub fn from_rate(steps_per_second: f32) -> Self {
+        Self {
+            step: 1. / steps_per_second,
+            tick: 0,
+            accumulator: 0.,
+            looping: false,
+        }
+    }
+
+    /// Number of steps run so far. The current step, while the simulation is running.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Simulated time so far, in seconds
+    pub fn elapsed(&self) -> f64 {
+        self.tick as f64 * self.step as f64
+    }
+
+    /// How far the frame is between the last step and the next, from 0 to 1.
+    /// Only meant for rendering, the simulation itself must not depend on it.
+    pub fn overstep(&self) -> f32 {
+        (self.accumulator / self.step as f64).min(1.) as f32
+    }
+
+    /// Queues `steps` more steps, to run on top of the frame time on the next update.
+    /// Lets apps without a real clock, like the headless runner, drive the simulation.
+    pub fn advance(&mut self, steps: u32) {
+        self.accumulator += steps as f64 * self.step as f64;
+    }
+
+    fn should_step(
+        mut clock: ResMut<SimulationClock>,
+        mut assets: ResMut<SimulationAssets>,
+        asset_server: Option<Res<AssetServer>>,
+        time: Res<Time>,
+    ) -> ShouldRun {
+        // Time spent loading isn't simulated
+        if !assets.update(asset_server.as_deref()) {
+            return ShouldRun::No;
+        }
+
+        let step = clock.step as f64;
+        if !clock.looping {
+            clock.accumulator += time
+                .delta_seconds_f64()
+                .min(step * Self::MAX_STEPS_PER_FRAME);
+        }
+
+        if clock.accumulator >= step {
+            clock.accumulator -= step;
+            clock.tick += 1;
+            clock.looping = true;
+            ShouldRun::YesAndCheckAgain
+        } else {
+            clock.looping = false;
+            ShouldRun::No
+        }
+    }
+}
+
+/// Assets the simulation needs, like pattern and bullet type files, to be added at startup.
+/// The simulation doesn't step, record or play back until they have all loaded (or failed to),
+/// so how long loading takes can't change what happens in it.
+#[derive(Resource, Default)]
+pub struct SimulationAssets {
+    handles: Vec<HandleId>,
+    loaded: bool,
+}
+
+impl SimulationAssets {
+    pub fn add(&mut self, handle: impl Into<HandleId>) {
+        self.handles.push(handle.into());
+    }
+
+    /// Stays true once everything has loaded, so reloading an asset doesn't pause the simulation.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    fn update(&mut self, asset_server: Option<&AssetServer>) -> bool {
+        if !self.loaded {
+            self.loaded = asset_server.map_or(true, |asset_server| {
+                self.handles.iter().all(|handle| {
+                    matches!(
+                        asset_server.get_load_state(*handle),
+                        LoadState::Loaded | LoadState::Failed
+                    )
+                })
+            });
+        }
+        self.loaded
+    }
+}
+
+/// Seeds everything random in the simulation. Changes every session, unless replaying one.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SimulationSeed(pub u64);
+
+impl Default for SimulationSeed {
+    fn default() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self(now.as_nanos() as u64)
+    }
+}
+
+/// Adding systems and events to the simulation.
+pub trait SimulationApp {
+    fn add_system_to_simulation<Params>(
+        &mut self,
+        stage: SimulationStage,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self;
+
+    /// Like [`App::add_event`], but the events are kept for two steps rather than two frames,
+    /// so simulation systems can't miss them on frames without a step.
+    fn add_simulation_event<T: Event>(&mut self) -> &mut Self;
+}
+
+impl SimulationApp for App {
+    fn add_system_to_simulation<Params>(
+        &mut self,
+        stage: SimulationStage,
+        system: impl IntoSystemDescriptor<Params>,
+    ) -> &mut Self {
+        self.stage(Simulation, |schedule: &mut Schedule| {
+            schedule.add_system_to_stage(stage, system)
+        })
+    }
+
+    fn add_simulation_event<T: Event>(&mut self) -> &mut Self {
+        if !self.world.contains_resource::<Events<T>>() {
+            self.init_resource::<Events<T>>()
+                .add_system_to_simulation(SimulationStage::PreTick, Events::<T>::update_system);
+        }
+        self
+    }
+}
+
+/// Smooths out the movement of a simulated entity between steps.
+/// The entity's own transform stays the simulated one: its children get moved instead,
+/// so they are drawn between its last two positions. They must otherwise stay at its origin.
+#[derive(Component, Default)]
+pub struct Interpolated {
+    previous: Option<Vec3>,
+}
+
+impl Interpolated {
+    fn store_previous(mut query: Query<(&mut Interpolated, &Transform)>) {
+        for (mut interpolated, transform) in query.iter_mut() {
+            interpolated.previous = Some(transform.translation);
+        }
+    }
+
+    fn interpolate(
+        clock: Res<SimulationClock>,
+        parent_query: Query<(&Interpolated, &Transform, &Children)>,
+        mut child_query: Query<&mut Transform, Without<Interpolated>>,
+    ) {
+        for (interpolated, transform, children) in parent_query.iter() {
+            let Some(previous) = interpolated.previous else { continue };
+
+            let offset = (previous - transform.translation) * (1. - clock.overstep());
+            let local_offset = transform.rotation.inverse() * offset / transform.scale;
+            for child in children.iter() {
+                if let Ok(mut child_tr) = child_query.get_mut(*child) {
+                    child_tr.translation = local_offset;
+                }
+            }
+        }
+    }
+}
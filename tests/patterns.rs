@@ -0,0 +1,215 @@
+//! Fires every pattern in `assets/patterns/` and checks where its bullets are at a few points in time
+//! against the snapshots in `tests/snapshots/`.
+//!
+//! After a change that's meant to move bullets, bless the new positions with:
+//!
+//! ```sh
+//! BLESS=1 cargo test --test patterns
+//! ```
+
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use simidi::{bullet::FirePattern, headless::HeadlessSimulation};
+
+const STEPS_PER_SECOND: f32 = 60.;
+/// Steps after firing at which bullets are sampled
+const SAMPLE_TICKS: [u64; 6] = [1, 15, 30, 60, 120, 180];
+/// Bullets kept per pool and sample, spread evenly over the pool
+const MAX_SAMPLED_BULLETS: usize = 64;
+/// How far positions (in units) and rotations (in radians) may drift from the snapshot
+const TOLERANCE: f32 = 0.01;
+
+/// Bullets of one type at one point in time.
+#[derive(Debug, PartialEq)]
+struct Sample {
+    tick: u64,
+    bullet_type: String,
+    /// Live bullets in the pool, all of them rather than just the sampled ones
+    count: usize,
+    bullets: Vec<[f32; 3]>,
+}
+
+fn manifest_dir() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn pattern_files() -> Vec<PathBuf> {
+    let mut files: Vec<_> = fs::read_dir(manifest_dir().join("assets/patterns"))
+        .expect("couldn't read assets/patterns")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.to_string_lossy().ends_with(".pattern.json"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// `assets/patterns/ring.pattern.json` is snapshotted in `tests/snapshots/ring.snap`.
+fn snapshot_path(pattern: &Path) -> PathBuf {
+    let file_name = pattern.file_name().unwrap().to_string_lossy();
+    let name = file_name.trim_end_matches(".pattern.json");
+    manifest_dir().join(format!("tests/snapshots/{name}.snap"))
+}
+
+/// Fires the pattern from the origin, with the player where the game spawns it.
+fn run_pattern(pattern: &Path) -> Result<Vec<Sample>, String> {
+    let mut simulation = HeadlessSimulation::new(STEPS_PER_SECOND);
+    simulation.spawn_player(Vec2::new(0., 100.));
+    let handle = simulation.load_pattern(pattern.strip_prefix(manifest_dir()).unwrap())?;
+    simulation.fire(FirePattern::new(handle));
+
+    let mut samples = vec![];
+    for tick in 1..=*SAMPLE_TICKS.last().unwrap() {
+        simulation.step();
+        if !SAMPLE_TICKS.contains(&tick) {
+            continue;
+        }
+
+        for pool in simulation.pools() {
+            let states: Vec<_> = pool.live_states().collect();
+            let stride = (states.len() / MAX_SAMPLED_BULLETS).max(1);
+            samples.push(Sample {
+                tick,
+                bullet_type: pool.bullet_type().to_string(),
+                count: states.len(),
+                bullets: states
+                    .iter()
+                    .step_by(stride)
+                    .take(MAX_SAMPLED_BULLETS)
+                    .map(|state| state.to_array())
+                    .collect(),
+            });
+        }
+    }
+
+    Ok(samples)
+}
+
+/// One `tick <tick> <bullet type> <count>` line per sample, followed by its bullets' `x y rotation`.
+fn write_snapshot(samples: &[Sample]) -> String {
+    let mut snapshot = String::new();
+    for sample in samples {
+        writeln!(
+            snapshot,
+            "tick {} {} {}",
+            sample.tick, sample.bullet_type, sample.count
+        )
+        .unwrap();
+        for [x, y, rotation] in &sample.bullets {
+            writeln!(snapshot, "{x:.4} {y:.4} {rotation:.4}").unwrap();
+        }
+    }
+    snapshot
+}
+
+fn parse_snapshot(snapshot: &str) -> Option<Vec<Sample>> {
+    let mut samples: Vec<Sample> = vec![];
+    for line in snapshot.lines().filter(|line| !line.is_empty()) {
+        if let Some(header) = line.strip_prefix("tick ") {
+            let mut words = header.split(' ');
+            samples.push(Sample {
+                tick: words.next()?.parse().ok()?,
+                bullet_type: words.next()?.to_string(),
+                count: words.next()?.parse().ok()?,
+                bullets: vec![],
+            });
+        } else {
+            let mut words = line.split(' ');
+            let mut number = || words.next()?.parse::<f32>().ok();
+            let bullet = [number()?, number()?, number()?];
+            samples.last_mut()?.bullets.push(bullet);
+        }
+    }
+    Some(samples)
+}
+
+/// Describes the first difference between the snapshot and the run, if any.
+fn compare(expected: &[Sample], actual: &[Sample]) -> Option<String> {
+    for (expected, actual) in expected.iter().zip(actual) {
+        let at = format!("at tick {}, {}", expected.tick, expected.bullet_type);
+        if (expected.tick, &expected.bullet_type) != (actual.tick, &actual.bullet_type) {
+            return Some(format!(
+                "{at}: found {} bullets at tick {} instead",
+                actual.bullet_type, actual.tick
+            ));
+        }
+        if expected.count != actual.count {
+            return Some(format!(
+                "{at}: {} bullets instead of {}",
+                actual.count, expected.count
+            ));
+        }
+
+        for (i, (expected, actual)) in expected.bullets.iter().zip(&actual.bullets).enumerate() {
+            if expected
+                .iter()
+                .zip(actual)
+                .any(|(expected, actual)| (expected - actual).abs() > TOLERANCE)
+            {
+                return Some(format!(
+                    "{at}: sampled bullet {i} is at {actual:?} instead of {expected:?}"
+                ));
+            }
+        }
+    }
+
+    if expected.len() != actual.len() {
+        return Some(format!(
+            "{} samples instead of {}",
+            actual.len(),
+            expected.len()
+        ));
+    }
+    None
+}
+
+#[test]
+fn patterns_match_snapshots() {
+    let bless = std::env::var_os("BLESS").is_some();
+    let mut failures = vec![];
+
+    for pattern in pattern_files() {
+        let name = pattern.file_name().unwrap().to_string_lossy().into_owned();
+        let samples = match run_pattern(&pattern) {
+            Ok(samples) => samples,
+            Err(error) => {
+                failures.push(format!("{name}: {error}"));
+                continue;
+            }
+        };
+
+        let snapshot_path = snapshot_path(&pattern);
+        if bless {
+            fs::create_dir_all(snapshot_path.parent().unwrap()).unwrap();
+            fs::write(&snapshot_path, write_snapshot(&samples)).unwrap();
+            continue;
+        }
+
+        let Ok(snapshot) = fs::read_to_string(&snapshot_path) else {
+            failures.push(format!("{name}: no snapshot yet"));
+            continue;
+        };
+        let Some(expected) = parse_snapshot(&snapshot) else {
+            failures.push(format!(
+                "{name}: couldn't parse {}",
+                snapshot_path.display()
+            ));
+            continue;
+        };
+
+        if let Some(difference) = compare(&expected, &samples) {
+            failures.push(format!("{name}: {difference}"));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "patterns don't match their snapshots:\n{}\n\nIf the changes are intended, bless them with \
+         `BLESS=1 cargo test --test patterns`",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,145 @@
+//! Records a session with the keyboard, plays it back, and checks that both end up in the same state.
+
+use std::{
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{app::AppExit, prelude::*};
+use simidi::{
+    editor::{EditorState, UIFocus},
+    headless::HeadlessSimulation,
+    player::{Player, PlayerPlugin},
+    replay::{Replay, ReplayMode, ReplayPlugin},
+    simulation::{SimulationApp, SimulationClock, SimulationStage},
+};
+
+const STEPS: u64 = 300;
+
+fn simulation(mode: ReplayMode) -> HeadlessSimulation {
+    let mut simulation = HeadlessSimulation::new(60.);
+    simulation
+        .app
+        .init_resource::<Input<KeyCode>>()
+        .init_resource::<UIFocus>()
+        .insert_resource(EditorState {
+            selected_pattern: "pattern8".into(),
+        })
+        .add_plugin(PlayerPlugin)
+        .add_plugin(ReplayPlugin { mode });
+    simulation.spawn_player(Vec2::new(0., 100.));
+    simulation
+}
+
+/// Where the player and every live bullet are, exactly.
+fn state(simulation: &mut HeadlessSimulation) -> Vec<(String, Vec<[u32; 3]>)> {
+    let world = &mut simulation.app.world;
+    let player = world
+        .query_filtered::<&Transform, With<Player>>()
+        .single(world)
+        .translation;
+    let mut state = vec![("player".into(), vec![player.to_array().map(f32::to_bits)])];
+
+    for pool in simulation.pools() {
+        let bullets = pool
+            .live_states()
+            .map(|bullet| bullet.to_array().map(f32::to_bits))
+            .collect();
+        state.push((pool.bullet_type().to_string(), bullets));
+    }
+    state
+}
+
+#[test]
+fn playback_matches_recording() {
+    let path = env::temp_dir().join(format!("simidi-replay-{}.json", std::process::id()));
+
+    // Record with steps queued from the very first frame, while the assets are still loading
+    let mut recording = simulation(ReplayMode::Record(path.clone()));
+    recording.seed(1234);
+    let started = Instant::now();
+    let mut frame = 0;
+    while recording.clock().tick() < STEPS {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "the recording didn't get through its steps"
+        );
+
+        // Fire before anything has loaded, then again and move once the steps are going
+        let tick = recording.clock().tick();
+        let mut keys = recording.app.world.resource_mut::<Input<KeyCode>>();
+        keys.clear();
+        keys.release(KeyCode::E);
+        if frame == 0 || tick == STEPS - 60 {
+            keys.press(KeyCode::E);
+        }
+        if (STEPS - 100..STEPS - 40).contains(&tick) {
+            keys.press(KeyCode::D);
+        } else {
+            keys.release(KeyCode::D);
+        }
+
+        recording.step();
+        frame += 1;
+        thread::sleep(Duration::from_millis(1));
+    }
+    recording.app.world.send_event(AppExit);
+    recording.app.update();
+    let expected = state(&mut recording);
+    let player = f32::from_bits(expected[0].1[0][0]);
+    assert!(player > 0., "the player didn't move right: x = {player}");
+
+    let replay = Replay::load(&path).expect("the recording wasn't saved");
+    fs::remove_file(&path).ok();
+    assert_eq!(replay.seed, 1234);
+    assert_eq!(replay.inputs.len() as u64, recording.clock().tick());
+    assert_eq!(
+        replay
+            .inputs
+            .iter()
+            .filter(|input| input.fire.is_some())
+            .count(),
+        2
+    );
+    assert!(replay.inputs.iter().any(|input| input.movement.x == 1));
+
+    // Play back only once everything has loaded
+    let steps = replay.inputs.len();
+    let mut playback = simulation(ReplayMode::Play(replay));
+    playback
+        .load_pattern("patterns/pattern8.pattern.json")
+        .unwrap();
+    for _ in 0..steps {
+        playback.step();
+    }
+
+    assert_eq!(state(&mut playback), expected);
+}
+
+#[test]
+fn recording_is_saved_on_panic() {
+    let path = env::temp_dir().join(format!("simidi-crash-{}.json", std::process::id()));
+
+    let mut recording = simulation(ReplayMode::Record(path.clone()));
+    recording
+        .app
+        .add_system_to_simulation(SimulationStage::Tick, |clock: Res<SimulationClock>| {
+            assert!(clock.tick() < 30, "crashed on purpose")
+        });
+    recording
+        .load_pattern("patterns/pattern8.pattern.json")
+        .unwrap();
+
+    let crashed = panic::catch_unwind(AssertUnwindSafe(|| {
+        for _ in 0..60 {
+            recording.step();
+        }
+    }));
+    assert!(crashed.is_err());
+
+    let replay = Replay::load(&path).expect("the recording wasn't saved");
+    fs::remove_file(&path).ok();
+    assert_eq!(replay.inputs.len(), 30);
+}